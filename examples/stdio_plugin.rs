@@ -0,0 +1,19 @@
+//! A plugin that echoes every message back to the process that started it
+//!
+//! On unix, stray prints are harmless: they end up on stderr rather than in the frame stream.
+//! Elsewhere the `println!` below would land inside the frame stream, as it runs on the thread that holds stdout.
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+
+fn main() {
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    let mut parent = stream::DualMessenger::stdio(config).expect("stdout is free");
+    while let Ok(message) = parent.read_next_message() {
+        println!("plugin received {} bytes", message.len());
+        let _framed = parent.write(&message).expect("the parent is listening");
+        parent.flush().expect("the parent is listening");
+    }
+}
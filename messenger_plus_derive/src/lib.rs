@@ -0,0 +1,218 @@
+//! `#[derive(Message)]` for `messenger_plus`
+//!
+//! Maps every variant of an enum onto its own pair of boundaries:
+//!
+//! ```ignore
+//! #[derive(Message)]
+//! enum Command {
+//!     #[message(boundary = "ping")]
+//!     Ping,
+//!     #[message(boundary = "data", ending = "enddata")]
+//!     Data(Vec<u8>),
+//! }
+//! ```
+//!
+//! The ending boundary defaults to the beginning boundary prefixed with `end`.
+//! Beginning boundaries must not differ only by trailing digits, like `msg` and `msg1`,
+//! as the length written after a boundary would make their frames ambiguous.
+//! Unit variants carry no payload; single-field variants carry a payload that is
+//! written through `AsRef<[u8]>` and read back through `From<Vec<u8>>`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr, Variant};
+
+#[proc_macro_derive(Message, attributes(message))]
+pub fn derive_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+struct VariantBoundaries<'a> {
+    variant: &'a Variant,
+    beginning: String,
+    ending: String,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => return Err(Error::new_spanned(&input.ident, "Message can only be derived for enums")),
+    };
+
+    let mut variants: Vec<VariantBoundaries> = Vec::new();
+    for variant in &data.variants {
+        let parsed = parse_variant(variant)?;
+        if variants.iter().any(|other| other.beginning == parsed.beginning) {
+            return Err(Error::new_spanned(variant, format!("the boundary {:?} is used by more than one variant", parsed.beginning)));
+        }
+        if let Some(other) = variants.iter().find(|other| ends_in_digits_past(&other.beginning, &parsed.beginning)) {
+            return Err(Error::new_spanned(variant, format!(
+                "the boundaries {:?} and {:?} differ only by trailing digits, so their frames cannot be told apart", other.beginning, parsed.beginning
+            )));
+        }
+        variants.push(parsed);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let boundary_pairs = variants.iter().map(|v| {
+        let (beginning, ending) = (&v.beginning, &v.ending);
+        quote! { (#beginning, #ending) }
+    });
+
+    let boundary_arms = variants.iter().map(|v| {
+        let ident = &v.variant.ident;
+        let (beginning, ending) = (&v.beginning, &v.ending);
+        quote! { #name::#ident { .. } => (#beginning, #ending), }
+    });
+
+    let payload_arms = variants.iter().map(|v| {
+        let ident = &v.variant.ident;
+        match v.variant.fields {
+            Fields::Unit => quote! { #name::#ident => &[], },
+            Fields::Unnamed(_) => quote! {
+                #name::#ident(ref payload) => ::std::convert::AsRef::<[u8]>::as_ref(payload),
+            },
+            Fields::Named(ref fields) => {
+                let field = &fields.named[0].ident;
+                quote! { #name::#ident { ref #field } => ::std::convert::AsRef::<[u8]>::as_ref(#field), }
+            }
+        }
+    });
+
+    let from_frame_arms = variants.iter().map(|v| {
+        let ident = &v.variant.ident;
+        let beginning = &v.beginning;
+        match v.variant.fields {
+            Fields::Unit => quote! { #beginning => ::std::result::Result::Ok(#name::#ident), },
+            Fields::Unnamed(_) => quote! {
+                #beginning => ::std::result::Result::Ok(#name::#ident(::std::convert::From::from(payload))),
+            },
+            Fields::Named(ref fields) => {
+                let field = &fields.named[0].ident;
+                quote! {
+                    #beginning => ::std::result::Result::Ok(#name::#ident { #field: ::std::convert::From::from(payload) }),
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::messenger_plus::stream::Message for #name #ty_generics #where_clause {
+            fn boundaries() -> &'static [(&'static str, &'static str)] {
+                &[#(#boundary_pairs),*]
+            }
+
+            fn boundary(&self) -> (&'static str, &'static str) {
+                match *self {
+                    #(#boundary_arms)*
+                }
+            }
+
+            fn payload(&self) -> &[u8] {
+                match *self {
+                    #(#payload_arms)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn from_frame(beginning_boundary: &str, payload: ::std::vec::Vec<u8>) -> ::messenger_plus::stream::Result<Self> {
+                match beginning_boundary {
+                    #(#from_frame_arms)*
+                    other => ::std::result::Result::Err(::messenger_plus::stream::Error::from(
+                        ::messenger_plus::stream::ErrorKind::UnknownBoundary(::std::string::String::from(other))
+                    )),
+                }
+            }
+        }
+    })
+}
+
+fn parse_variant(variant: &Variant) -> syn::Result<VariantBoundaries<'_>> {
+    match variant.fields {
+        Fields::Unit => {}
+        ref fields if fields.is_empty() => {
+            return Err(Error::new_spanned(variant, "Message variants with braces or parentheses need one payload field; use a unit variant for none"));
+        }
+        ref fields if fields.len() > 1 => {
+            return Err(Error::new_spanned(variant, "Message variants can hold at most one payload field"));
+        }
+        _ => {}
+    }
+
+    let mut beginning: Option<LitStr> = None;
+    let mut ending: Option<LitStr> = None;
+    for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("message")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("boundary") {
+                beginning = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("ending") {
+                ending = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `boundary` or `ending`"))
+            }
+        })?;
+    }
+
+    let beginning = match beginning {
+        Some(lit) => lit.value(),
+        None => return Err(Error::new_spanned(variant, "missing #[message(boundary = \"...\")] on variant")),
+    };
+    if beginning.is_empty() {
+        return Err(Error::new_spanned(variant, "a boundary must not be empty"));
+    }
+    let ending = ending.map_or_else(|| format!("end{}", beginning), |lit| lit.value());
+
+    Ok(VariantBoundaries {
+        variant,
+        beginning,
+        ending,
+    })
+}
+
+/// Returns whether one boundary is the other followed by digits, which the length after it would run into
+fn ends_in_digits_past(first: &str, second: &str) -> bool {
+    let (shorter, longer) = if first.len() <= second.len() { (first, second) } else { (second, first) };
+    longer.len() > shorter.len() && longer.starts_with(shorter) && longer[shorter.len()..].bytes().all(|byte| byte.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+    use syn::DeriveInput;
+
+    fn expand_error(source: &str) -> String {
+        let input: DeriveInput = syn::parse_str(source).unwrap();
+        expand(&input).expect_err("the derive should have been rejected").to_string()
+    }
+
+    #[test]
+    fn empty_field_lists_are_rejected_test() {
+        let message = "Message variants with braces or parentheses need one payload field; use a unit variant for none";
+        assert_eq!(expand_error("enum E { #[message(boundary = \"a\")] A {} }"), message);
+        assert_eq!(expand_error("enum E { #[message(boundary = \"a\")] A() }"), message);
+    }
+
+    #[test]
+    fn several_fields_are_rejected_test() {
+        assert_eq!(expand_error("enum E { #[message(boundary = \"a\")] A(Vec<u8>, Vec<u8>) }"), "Message variants can hold at most one payload field");
+    }
+
+    #[test]
+    fn boundaries_differing_by_digits_are_rejected_test() {
+        assert_eq!(
+            expand_error("enum E { #[message(boundary = \"msg1\")] A, #[message(boundary = \"msg\")] B }"),
+            "the boundaries \"msg1\" and \"msg\" differ only by trailing digits, so their frames cannot be told apart"
+        );
+    }
+}
@@ -1,2 +1,6 @@
+extern crate sha3;
+
 pub mod stream;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+#[cfg(feature = "testing")]
+pub mod testing;
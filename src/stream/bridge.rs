@@ -0,0 +1,91 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use super::{ErrorKind, MessageReader, MessageWriter, Result, Shutdown};
+
+impl<W: Write + Send + 'static> MessageWriter<W> {
+
+    /// Spawns a thread that writes every message received from `receiver`
+    ///
+    /// Each message is flushed as soon as it is written. The thread stops once every sender has hung up,
+    /// and its handle then gives back the MessageWriter so the caller can close it or keep writing.
+    ///
+    /// # Errors
+    /// The handle will return Err with the first error that writing or flushing reports.
+    /// The receiver is dropped with it, so later sends fail instead of queueing up.
+    pub fn spawn_from(mut self, receiver: Receiver<Vec<u8>>) -> JoinHandle<io::Result<MessageWriter<W>>> {
+        thread::spawn(move || {
+            for message in receiver {
+                let _framed = self.write(&message)?;
+                self.flush()?;
+            }
+            Ok(self)
+        })
+    }
+}
+
+/// The thread started by `MessageReader::spawn_into`
+///
+/// Stopping it takes effect at the reader's next read timeout, like `Shutdown` does for `MessageListener::serve`,
+/// so a reader without a read timeout only notices once the next message arrives or the stream ends.
+#[derive(Debug)]
+pub struct ReaderThread<T> where T: Read {
+    shutdown: Shutdown,
+    handle: JoinHandle<Result<MessageReader<T>>>,
+}
+
+impl<T: Read> ReaderThread<T> {
+
+    /// Asks the thread to stop; it gives back the MessageReader once its current read times out
+    pub fn stop(&self) {
+        self.shutdown.shutdown();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the thread to stop and returns what it gave back
+    ///
+    /// # Errors
+    /// This method will return Err if the thread panicked.
+    pub fn join(self) -> thread::Result<Result<MessageReader<T>>> {
+        self.handle.join()
+    }
+}
+
+impl<T: Read + Send + 'static> MessageReader<T> {
+
+    /// Spawns a thread that forwards every message read to `sender`
+    ///
+    /// The thread stops when the stream ends, when the peer closes it, when the receiver hangs up, or when `stop` is
+    /// called on the returned ReaderThread, which then gives back the MessageReader. A hung up receiver is only noticed
+    /// once the next message arrives, and a stop at the next read timeout, as the thread is usually blocked reading until then.
+    ///
+    /// # Errors
+    /// The thread will return Err with the first error reading reports, other than the stream ending or closing or a read timing out.
+    /// The sender is dropped with it, so the receiving side sees the channel hang up.
+    pub fn spawn_into(mut self, sender: Sender<Vec<u8>>) -> ReaderThread<T> {
+        let shutdown = Shutdown::new();
+        let stopped = shutdown.clone();
+        let handle = thread::spawn(move || {
+            while !stopped.is_shutdown() {
+                match self.read_next_message() {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            return Ok(self);
+                        }
+                    }
+                    Err(e) => match *e.kind() {
+                        ErrorKind::BufferEmpty | ErrorKind::Closed { .. } => return Ok(self),
+                        _ if e.is_read_timeout() => {}
+                        _ => return Err(e),
+                    },
+                }
+            }
+            Ok(self)
+        });
+        ReaderThread { shutdown, handle }
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::{self, Cursor};
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+use super::frame_header::decode_message;
+use super::sequence::SequenceTracker;
+use super::write_stream::build_message_frame;
+use super::{read_tagged_message_from_reader, Error, ErrorKind, Result, StreamConfiguration};
+
+/// The largest payload a UDP datagram over IPv4 can carry
+const MAX_UDP_DATAGRAM_SIZE: usize = 65507;
+
+/// Connected sockets that send and receive whole datagrams
+pub trait DatagramSocket {
+    fn send_datagram(&self, datagram: &[u8]) -> io::Result<usize>;
+    fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl DatagramSocket for UdpSocket {
+    fn send_datagram(&self, datagram: &[u8]) -> io::Result<usize> {
+        self.send(datagram)
+    }
+
+    fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf)
+    }
+}
+
+#[cfg(unix)]
+impl DatagramSocket for UnixDatagram {
+    fn send_datagram(&self, datagram: &[u8]) -> io::Result<usize> {
+        self.send(datagram)
+    }
+
+    fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf)
+    }
+}
+
+/// Exchanges messages over a connected datagram socket, one frame per datagram
+///
+/// Frames are laid out exactly as on a stream with the same configuration, but every datagram must hold one whole frame
+/// and nothing else. A malformed datagram is reported on its own and never affects the ones after it.
+/// With sequence numbers enabled, lost, repeated and reordered datagrams are reported like `MessageReader::read_next_message`.
+#[derive(Debug)]
+pub struct DatagramMessenger<S> where S: DatagramSocket {
+    configuration: StreamConfiguration,
+    socket: S,
+    max_datagram_size: usize,
+    next_sequence: u64,
+    sequence: SequenceTracker,
+}
+
+impl<S: DatagramSocket> DatagramMessenger<S> {
+
+    /// Initializes a new DatagramMessenger over a connected socket
+    pub fn new(config: StreamConfiguration, socket: S) -> DatagramMessenger<S> {
+        DatagramMessenger {
+            configuration: config,
+            socket,
+            max_datagram_size: MAX_UDP_DATAGRAM_SIZE,
+            next_sequence: 0,
+            sequence: SequenceTracker::default(),
+        }
+    }
+
+    pub fn get_socket(&self) -> &S {
+        &self.socket
+    }
+
+    /// Sets the largest datagram that is sent or received, 65507 bytes by default
+    ///
+    /// Received datagrams larger than this are reported as malformed.
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    pub fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
+
+    /// Sends a message as a single datagram, returning the size of the datagram
+    ///
+    /// # Errors
+    /// This method will return an `InvalidInput` error if the framed message does not fit in one datagram.
+    /// This method will return Err if the socket does not send the whole datagram.
+    pub fn send(&mut self, message: &[u8]) -> io::Result<usize> {
+        let datagram = build_message_frame(&self.configuration, &mut self.next_sequence, message);
+        if datagram.len() > self.max_datagram_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the framed message does not fit in one datagram"));
+        }
+        if self.socket.send_datagram(&datagram)? != datagram.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "the socket sent part of the datagram"));
+        }
+        Ok(datagram.len())
+    }
+
+    /// Receives the next datagram and returns the message in it
+    ///
+    /// # Errors
+    /// This method will return `MalformedDatagram` if the datagram holds less or more than one frame, or is larger than
+    /// the maximum datagram size. A datagram whose boundaries or delimiters do not match is reported like a malformed frame on a stream,
+    /// and with hashing enabled, one whose message was corrupted is reported as `HashMismatch`.
+    /// In every case the datagram is dropped and the next receive starts on the following one.
+    /// This method will return `IOError` if the socket fails.
+    pub fn receive(&mut self) -> Result<Vec<u8>> {
+        if let Some(message) = self.sequence.take_held_back() {
+            return Ok(message);
+        }
+        // one byte of room past the maximum tells a datagram of exactly the maximum size from a larger one cut short
+        let mut datagram = vec![0; self.max_datagram_size + 1];
+        let size = self.socket.recv_datagram(&mut datagram)?;
+        if size > self.max_datagram_size {
+            return Err(Error::from(ErrorKind::MalformedDatagram));
+        }
+        datagram.truncate(size);
+        let payload = parse_datagram(&self.configuration, datagram)?;
+        decode_message(&self.configuration, &mut self.sequence, payload)
+    }
+
+    /// Receives datagrams until one holds a valid message, dropping the rest
+    ///
+    /// # Errors
+    /// This method will return Err only if the socket fails.
+    pub fn receive_valid(&mut self) -> Result<Vec<u8>> {
+        loop {
+            match self.receive() {
+                Ok(message) => return Ok(message),
+                Err(e) => match *e.kind() {
+                    ErrorKind::IOError(_) => return Err(e),
+                    _ => continue,
+                },
+            }
+        }
+    }
+}
+
+/// Reads the one frame a datagram must hold, returning its payload
+fn parse_datagram(configuration: &StreamConfiguration, datagram: Vec<u8>) -> Result<Vec<u8>> {
+    let size = datagram.len();
+    let mut cursor = Cursor::new(datagram);
+    let boundaries = [(configuration.beginning_boundary.as_str(), configuration.ending_boundary.as_str())];
+    let (_, payload) = match read_tagged_message_from_reader(&mut cursor, &configuration.delimiter_string, &boundaries) {
+        Ok(v) => v,
+        // running out of datagram is a short frame rather than a failing socket
+        Err(e) => match *e.kind() {
+            ErrorKind::IOError(_) | ErrorKind::BufferEmpty => return Err(Error::from(ErrorKind::MalformedDatagram)),
+            _ => return Err(e),
+        },
+    };
+    if cursor.position() as usize != size {
+        return Err(Error::from(ErrorKind::MalformedDatagram));
+    }
+    Ok(payload)
+}
@@ -0,0 +1,77 @@
+use std::io::Read;
+use super::{MultiMessageReader, Result, ErrorKind};
+
+/// Routes each message on a stream to the handler registered for its beginning boundary
+///
+/// This allows several kinds of message to be interleaved on one pipe, with the beginning boundary telling them apart.
+pub struct Dispatcher<'a, T> where T: Read {
+    reader: MultiMessageReader<T>,
+    handlers: Vec<Box<dyn FnMut(Vec<u8>) + 'a>>,
+}
+
+impl<'a, T: Read> Dispatcher<'a, T> {
+
+    /// Initializes a new Dispatcher with no handlers
+    pub fn new<V: Into<String>>(delimiter_string: V, reader: T) -> Dispatcher<'a, T> {
+        Dispatcher {
+            reader: MultiMessageReader::new(delimiter_string.into(), Vec::new(), reader),
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Registers the handler invoked for messages between the given boundaries
+    ///
+    /// Registering a beginning boundary a second time replaces its handler and ending boundary.
+    ///
+    /// # Panics
+    /// Panics if the beginning boundary and a registered one are the same but for digits at the end,
+    /// like `MultiMessageReader::add_boundaries`.
+    pub fn register<V, F>(&mut self, beg_bound: V, end_bound: V, handler: F) -> &mut Dispatcher<'a, T> where V: Into<String>, F: FnMut(Vec<u8>) + 'a {
+        let (beg_bound, end_bound) = (beg_bound.into(), end_bound.into());
+        match self.reader.boundaries().iter().position(|(beg, _)| *beg == beg_bound) {
+            Some(index) => {
+                self.reader.boundaries_mut()[index].1 = end_bound;
+                self.handlers[index] = Box::new(handler);
+            }
+            None => {
+                self.reader.add_boundaries(beg_bound, end_bound);
+                self.handlers.push(Box::new(handler));
+            }
+        }
+        self
+    }
+
+    /// Reads the next message and hands it to its handler
+    ///
+    /// Returns the beginning boundary of the dispatched message.
+    ///
+    /// # Errors
+    /// See `MultiMessageReader::read_next_message`.
+    /// Messages without a registered handler are reported as `UnknownBoundary` and skipped, so dispatching can carry on afterwards.
+    pub fn dispatch_next(&mut self) -> Result<String> {
+        let tagged = self.reader.read_next_message()?;
+        let index = self.reader.boundaries().iter()
+            .position(|(beg, _)| *beg == tagged.beginning_boundary)
+            .expect("the reader only accepts registered boundaries");
+        (self.handlers[index])(tagged.message);
+        Ok(tagged.beginning_boundary)
+    }
+
+    /// Dispatches messages until the stream runs out
+    ///
+    /// # Errors
+    /// This method returns the first error other than `BufferEmpty` that `dispatch_next` reports.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            match self.dispatch_next() {
+                Ok(_) => continue,
+                Err(ref e) if *e.kind() == ErrorKind::BufferEmpty => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn get_reader(&self) -> &T {
+        self.reader.get_reader()
+    }
+}
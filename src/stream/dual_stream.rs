@@ -1,12 +1,39 @@
 use std::io::{Read, Write};
 use std::io;
+use std::result;
+use std::time::{Duration, Instant};
 use super::stream_configuration::StreamConfiguration;
-use super::{Result, InternalMessageReader, InternalMessageWriter};
+use super::frame_header::{decode_message, encode_remote_error, decode_remote_error, decode_close_reason, prepend_header_fields, split_header_fields};
+use super::heartbeat::HeartbeatState;
+use super::sequence::SequenceTracker;
+use super::split::{self, ReadHalf, WriteHalf, TryCloneStream};
+use super::timeout::DeadlineReader;
+use super::write_stream::{build_frame, write_frame, write_message, write_pending};
+use super::{MessageReader, MessageWriter, Error, ErrorKind, Heartbeat, Result, Message, PartialFrame, ReadTimeout};
+
+// indices of the frame kinds `read_next_message` reads
+const MESSAGE_FRAME: usize = 0;
+const PING_FRAME: usize = 1;
+const PONG_FRAME: usize = 2;
+const CLOSE_FRAME: usize = 3;
+
+/// The reading and writing halves of a DualMessenger made by `split`
+pub type SplitHalves<T> = (MessageReader<ReadHalf<T>>, MessageWriter<WriteHalf<T>>);
+
+/// The reading and writing halves of a DualMessenger made by `try_split`
+pub type ClonedHalves<T> = (MessageReader<T>, MessageWriter<T>);
 
 #[derive(Debug)]
 pub struct DualMessenger<T> where T: Read + Write {
     configuration: StreamConfiguration,
     channel: Box<T>,
+    sequence: SequenceTracker,
+    next_sequence: u64,
+    heartbeat: Option<HeartbeatState>,
+    closed: bool,
+    peer_closed: Option<Option<u64>>,
+    partial: PartialFrame,
+    pending: Vec<u8>,
 }
 
 impl<T> DualMessenger<T> where T: Read + Write {
@@ -20,9 +47,17 @@ impl<T> DualMessenger<T> where T: Read + Write {
                 delimiter_string: delimiter_string.into(),
                 beginning_boundary: beg_bound.into(),
                 ending_boundary: end_bound.into(),
-                hashing_enabled: hashing_enabled,
+                hashing_enabled,
+                sequence_numbers_enabled: false,
             },
             channel: Box::new(channel),
+            sequence: SequenceTracker::default(),
+            next_sequence: 0,
+            heartbeat: None,
+            closed: false,
+            peer_closed: None,
+            partial: PartialFrame::default(),
+            pending: Vec::new(),
         }
     }
 
@@ -30,6 +65,13 @@ impl<T> DualMessenger<T> where T: Read + Write {
         DualMessenger {
             configuration: config,
             channel: Box::new(channel),
+            sequence: SequenceTracker::default(),
+            next_sequence: 0,
+            heartbeat: None,
+            closed: false,
+            peer_closed: None,
+            partial: PartialFrame::default(),
+            pending: Vec::new(),
         }
     }
  
@@ -42,9 +84,266 @@ impl<T> DualMessenger<T> where T: Read + Write {
     /// This method will return None if it cannot find a message and the stream ends (typically due to EOF).
     /// This method can hang if no new data is sent through the pipe as `Read` can block.
     /// This method can produce irratic results if the `boundary_start` or `boundary_end` is found within the message.
+    /// This method will return `UnknownBoundary`, naming the boundary, if a frame begins with a boundary other than the configured one;
+    /// that frame is skipped, so the next read starts on the one after it.
+    /// With sequence numbers enabled, this method reports messages that arrive out of turn like `MessageReader::read_next_message`.
+    /// With heartbeats enabled, this method will return `PeerTimeout` once nothing has been heard from the peer for too long.
+    /// This method will return `Closed` once the peer has closed the stream, and on every read after that.
+    /// This method will return `Remote` when the peer sent an error frame in place of a message.
     pub fn read_next_message(&mut self) -> Result<Vec<u8>> {
-        let mut internal_reader = InternalMessageReader::new(self.channel.as_mut(), &self.configuration);
-        internal_reader.read_next_message()
+        if let Some(message) = self.sequence.take_held_back() {
+            return Ok(message);
+        }
+        let payload = self.read_past_control_frames()?;
+        decode_message(&self.configuration, &mut self.sequence, payload)
+    }
+
+    /// Turns heartbeats on or off
+    ///
+    /// With heartbeats on, `read_next_message` pings the peer every interval. Pings are answered by any DualMessenger
+    /// while it reads, and neither pings nor their answers are ever returned as messages.
+    /// Pings can only be sent while reading, so the stream needs a read timeout shorter than the interval
+    /// (such as `TcpStream::set_read_timeout`) to notice a silent peer.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat.map(HeartbeatState::new);
+    }
+
+    /// Returns how long the peer took to answer the latest ping that was answered
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        self.heartbeat.as_ref().and_then(|heartbeat| heartbeat.round_trip_time())
+    }
+
+    /// Stops sending, telling the peer with a close frame carrying an optional reason code
+    ///
+    /// The stream stays open for reading, so messages the peer sent before seeing the close frame can still be drained
+    /// until `read_next_message` returns `Closed`. Every write after this fails, and closing again does nothing.
+    pub fn close(&mut self, reason: Option<u64>) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let (close_beg, close_end) = self.configuration.control_boundaries("close");
+        let payload = prepend_header_fields(&self.configuration.delimiter_string, &reason.into_iter().collect::<Vec<_>>(), &[]);
+        self.write_between(&close_beg, &close_end, &payload)?;
+        self.flush()?;
+        self.closed = true;
+        Ok(())
+    }
+
+    /// Returns whether this side has closed the stream for sending
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Returns the reason the peer gave for closing the stream, once its close frame has been read
+    pub fn peer_closed(&self) -> Option<Option<u64>> {
+        self.peer_closed
+    }
+
+    fn read_past_control_frames(&mut self) -> Result<Vec<u8>> {
+        if let Some(reason) = self.peer_closed {
+            return Err(Error::from(ErrorKind::Closed { reason }));
+        }
+        let (ping_beg, ping_end) = self.configuration.control_boundaries("ping");
+        let (pong_beg, pong_end) = self.configuration.control_boundaries("pong");
+        let (close_beg, close_end) = self.configuration.control_boundaries("close");
+        let (error_beg, error_end) = self.configuration.control_boundaries("error");
+        loop {
+            if let Some(token) = self.heartbeat.as_mut().and_then(|heartbeat| heartbeat.ping_due()) {
+                self.write_control_frame(&ping_beg, &ping_end, token)?;
+            }
+
+            let boundaries = [
+                (self.configuration.beginning_boundary.as_str(), self.configuration.ending_boundary.as_str()),
+                (ping_beg.as_str(), ping_end.as_str()),
+                (pong_beg.as_str(), pong_end.as_str()),
+                (close_beg.as_str(), close_end.as_str()),
+                (error_beg.as_str(), error_end.as_str()),
+            ];
+            let (index, payload) = match self.partial.read_tagged_message(self.channel.as_mut(), &self.configuration.delimiter_string, &boundaries) {
+                Ok(v) => v,
+                // the peer is only declared dead once a read finds nothing waiting from it
+                Err(ref e) if e.is_read_timeout() && self.heartbeat.is_some() => {
+                    if self.heartbeat.as_ref().is_some_and(|heartbeat| heartbeat.peer_timed_out()) {
+                        return Err(Error::from(ErrorKind::PeerTimeout));
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(ref mut heartbeat) = self.heartbeat {
+                heartbeat.heard();
+            }
+
+            match index {
+                MESSAGE_FRAME => return Ok(payload),
+                PING_FRAME => {
+                    let (fields, _) = split_header_fields(&self.configuration.delimiter_string, 1, payload)?;
+                    self.write_control_frame(&pong_beg, &pong_end, fields[0])?;
+                }
+                PONG_FRAME => {
+                    let (fields, _) = split_header_fields(&self.configuration.delimiter_string, 1, payload)?;
+                    if let Some(ref mut heartbeat) = self.heartbeat {
+                        heartbeat.pong(fields[0]);
+                    }
+                }
+                CLOSE_FRAME => {
+                    let reason = decode_close_reason(&self.configuration.delimiter_string, payload)?;
+                    self.peer_closed = Some(reason);
+                    return Err(Error::from(ErrorKind::Closed { reason }));
+                }
+                _ => return Err(decode_remote_error(&self.configuration.delimiter_string, payload)),
+            }
+        }
+    }
+
+    /// Writes a control frame carrying a single number, unless this side has closed the stream
+    fn write_control_frame(&mut self, beg_bound: &str, end_bound: &str, field: u64) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let payload = prepend_header_fields(&self.configuration.delimiter_string, &[field], &[]);
+        self.write_between(beg_bound, end_bound, &payload)?;
+        self.flush()
+    }
+
+    /// Reads the next message from the DualMessenger as a typed message
+    ///
+    /// See `MessageReader::read_next_typed_message`.
+    pub fn read_next_typed_message<M: Message>(&mut self) -> Result<M> {
+        let boundaries = M::boundaries();
+        let (index, message) = self.read_next_tagged_message(boundaries)?;
+        M::from_frame(boundaries[index].0, message)
+    }
+
+    /// Writes a message between the given boundaries instead of the configured ones
+    ///
+    /// See `MessageWriter::write_between`.
+    pub fn write_between(&mut self, beg_bound: &str, end_bound: &str, buf: &[u8]) -> io::Result<usize> {
+        self.check_open()?;
+        self.finish_pending()?;
+        let frame = build_frame(&self.configuration.with_boundaries(beg_bound, end_bound), buf);
+        write_frame(self.channel.as_mut(), &mut self.pending, frame)
+    }
+
+    /// Writes an error frame carrying a code and a message in place of a message
+    ///
+    /// See `MessageWriter::send_error`.
+    pub fn send_error(&mut self, code: u64, message: &str) -> io::Result<usize> {
+        let (error_beg, error_end) = self.configuration.control_boundaries("error");
+        let payload = encode_remote_error(&self.configuration.delimiter_string, code, message);
+        self.write_between(&error_beg, &error_end, &payload)
+    }
+
+    /// Writes a typed message between the boundaries of its variant
+    ///
+    /// See `MessageWriter::send_message`.
+    pub fn send_message<M: Message>(&mut self, message: &M) -> io::Result<usize> {
+        let (beginning_boundary, ending_boundary) = message.boundary();
+        self.write_between(beginning_boundary, ending_boundary, message.payload())
+    }
+
+    /// Writes the rest of a frame that timed out on the MessageWriter this was reunited from
+    fn finish_pending(&mut self) -> io::Result<()> {
+        write_pending(self.channel.as_mut(), &mut self.pending).1
+    }
+
+    fn check_open(&self) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::from(Error::from(ErrorKind::Closed { reason: None })));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn configuration(&self) -> &StreamConfiguration {
+        &self.configuration
+    }
+
+    pub(crate) fn read_next_tagged_message(&mut self, boundaries: &[(&str, &str)]) -> Result<(usize, Vec<u8>)> {
+        self.partial.read_tagged_message(self.channel.as_mut(), &self.configuration.delimiter_string, boundaries)
+    }
+
+    /// Reads like `read_next_tagged_message`, giving up if the whole frame has not arrived by `deadline`
+    pub(crate) fn read_next_tagged_message_deadline(&mut self, boundaries: &[(&str, &str)], deadline: Instant) -> Result<(usize, Vec<u8>)> where T: ReadTimeout {
+        let mut reader = DeadlineReader::new(self.channel.as_mut(), deadline)?;
+        self.partial.read_tagged_message(&mut reader, &self.configuration.delimiter_string, boundaries)
+    }
+
+    /// Splits the DualMessenger into a MessageReader and a MessageWriter that can move to different threads
+    ///
+    /// Both halves share the stream through a lock, which each read and write takes only for its own duration.
+    /// A read that blocks on the stream still holds up the writer; streams that can be cloned should use `try_split`.
+    /// Sequence numbers and the close state carry over to the halves, but heartbeats stop.
+    pub fn split(self) -> SplitHalves<T> {
+        let (read_half, write_half) = split::halves(self.channel);
+        (
+            MessageReader::from_parts(self.configuration.clone(), read_half, self.sequence, self.peer_closed, self.partial),
+            MessageWriter::from_parts(self.configuration, write_half, self.next_sequence, self.closed, self.pending),
+        )
+    }
+
+    /// Puts the halves made by `split` back together
+    ///
+    /// # Errors
+    /// This method returns both halves unchanged if they were split from different DualMessengers.
+    pub fn reunite(reader: MessageReader<ReadHalf<T>>, writer: MessageWriter<WriteHalf<T>>) -> result::Result<DualMessenger<T>, Box<SplitHalves<T>>> {
+        let (configuration, read_half, sequence, peer_closed, partial) = reader.into_parts();
+        let (writer_configuration, write_half, next_sequence, closed, pending) = writer.into_parts();
+        match split::unsplit(read_half, write_half) {
+            Ok(channel) => Ok(DualMessenger {
+                configuration,
+                channel,
+                sequence,
+                next_sequence,
+                heartbeat: None,
+                closed,
+                peer_closed,
+                partial,
+                pending,
+            }),
+            Err((read_half, write_half)) => Err(Box::new((
+                MessageReader::from_parts(configuration, read_half, sequence, peer_closed, partial),
+                MessageWriter::from_parts(writer_configuration, write_half, next_sequence, closed, pending),
+            ))),
+        }
+    }
+
+    /// Splits the DualMessenger into a MessageReader and a MessageWriter over two handles to the same stream
+    ///
+    /// Unlike `split`, neither half ever waits on the other.
+    ///
+    /// # Errors
+    /// This method will return Err if the stream cannot be cloned.
+    pub fn try_split(self) -> io::Result<ClonedHalves<T>> where T: TryCloneStream {
+        let write_stream = self.channel.try_clone_stream()?;
+        let token = split::next_split_token();
+        let mut reader = MessageReader::from_parts(self.configuration.clone(), *self.channel, self.sequence, self.peer_closed, self.partial);
+        let mut writer = MessageWriter::from_parts(self.configuration, write_stream, self.next_sequence, self.closed, self.pending);
+        reader.set_split_token(token);
+        writer.set_split_token(token);
+        Ok((reader, writer))
+    }
+
+    /// Puts the halves made by `try_split` back together, keeping the reader's handle to the stream
+    ///
+    /// # Errors
+    /// This method returns both halves unchanged if they did not come from the same call to `try_split`.
+    pub fn reunite_cloned(reader: MessageReader<T>, writer: MessageWriter<T>) -> result::Result<DualMessenger<T>, Box<ClonedHalves<T>>> where T: TryCloneStream {
+        if reader.split_token().is_none() || reader.split_token() != writer.split_token() {
+            return Err(Box::new((reader, writer)));
+        }
+        let (configuration, channel, sequence, peer_closed, partial) = reader.into_parts();
+        let (_, _, next_sequence, closed, pending) = writer.into_parts();
+        Ok(DualMessenger {
+            configuration,
+            channel: Box::new(channel),
+            sequence,
+            next_sequence,
+            heartbeat: None,
+            closed,
+            peer_closed,
+            partial,
+            pending,
+        })
     }
 
     pub fn release(self) -> Box<T> {
@@ -54,11 +353,13 @@ impl<T> DualMessenger<T> where T: Read + Write {
 
 impl<T> Write for DualMessenger<T> where T: Read + Write {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let mut internal_writer = InternalMessageWriter::new(&self.configuration, self.channel.as_mut());
-        Ok(internal_writer.write(buf)?)
+        self.check_open()?;
+        self.finish_pending()?;
+        write_message(&self.configuration, self.channel.as_mut(), &mut self.pending, &mut self.next_sequence, buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        self.finish_pending()?;
         self.channel.flush()
     }
 }
\ No newline at end of file
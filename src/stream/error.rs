@@ -12,6 +12,20 @@ pub struct Error {
     internal: ErrorKind,
 }
 
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.internal
+    }
+
+    /// Returns whether this error only means that a read timed out before anything arrived
+    pub(crate) fn is_read_timeout(&self) -> bool {
+        match self.internal {
+            ErrorKind::IOError(ref e) => e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+}
+
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
         Error {
@@ -26,6 +40,15 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err.internal {
+            ErrorKind::IOError(e) => e,
+            _ => io::Error::other(err),
+        }
+    }
+}
+
 impl From<num::ParseIntError> for Error {
     fn from(err: num::ParseIntError) -> Error {
         Error::from(ErrorKind::IntParseError(err))
@@ -38,6 +61,7 @@ impl From<string::FromUtf8Error> for Error {
     }
 }
 
+#[allow(deprecated)]
 impl fmt::Display for Error {
     fn fmt(&self, fmter: &mut fmt::Formatter) -> fmt::Result {
         match self.internal {
@@ -49,12 +73,35 @@ impl fmt::Display for Error {
             ErrorKind::BeginningDoesntMatch => write!(fmter, "The beginning segments do not match"),
             ErrorKind::DelimiterDoesntMatch => write!(fmter, "The delimiters do not match"),
             ErrorKind::BufferEmpty => write!(fmter, "The buffer is empty"),
+            ErrorKind::EndingDoesntMatch => write!(fmter, "The ending segments do not match"),
+            ErrorKind::UnknownBoundary(ref boundary) => write!(fmter, "No message is known by the boundary {:?}", boundary),
+            ErrorKind::ChannelClosed(ref id) => write!(fmter, "Channel {} is closed", id),
+            ErrorKind::ChannelAlreadyOpen(ref id) => write!(fmter, "Channel {} is already open", id),
+            ErrorKind::FlowControlViolation(ref id) => write!(fmter, "The peer sent more than the receive window of channel {} allows", id),
+            ErrorKind::TimedOut => write!(fmter, "The operation timed out"),
+            ErrorKind::Disconnected => write!(fmter, "The connection to the peer was lost"),
+            ErrorKind::UnknownMethod(ref method) => write!(fmter, "The peer has no handler for the method {:?}", method),
+            ErrorKind::Cancelled => write!(fmter, "The call was cancelled"),
+            ErrorKind::StreamFailed(ref message) => write!(fmter, "The peer ended the stream with an error: {}", message),
+            ErrorKind::SequenceGap { expected, received } => write!(fmter, "Expected sequence number {} but received {}; the frames between were lost", expected, received),
+            ErrorKind::DuplicateSequence(ref sequence) => write!(fmter, "Sequence number {} was received twice", sequence),
+            ErrorKind::OutOfOrderSequence(ref sequence) => write!(fmter, "Sequence number {} arrived after later frames", sequence),
+            ErrorKind::HashMismatch => write!(fmter, "The message does not match the hash sent with it"),
+            ErrorKind::PeerTimeout => write!(fmter, "Nothing was heard from the peer within the heartbeat timeout"),
+            ErrorKind::Closed { reason: Some(reason) } => write!(fmter, "The stream was closed with reason {}", reason),
+            ErrorKind::Closed { reason: None } => write!(fmter, "The stream was closed"),
+            ErrorKind::Remote { code, ref message } => write!(fmter, "The peer reported error {}: {}", code, message),
+            ErrorKind::WriteTimedOut { written, remaining } => write!(fmter, "The write timed out after {} bytes with {} bytes of the frame left to send", written, remaining),
+            ErrorKind::UnknownSession(ref id) => write!(fmter, "The peer asked to resume session {}, which is not this one", id),
+            ErrorKind::ProcessDied { code: Some(code) } => write!(fmter, "The child process exited with code {} partway through a frame", code),
+            ErrorKind::ProcessDied { code: None } => write!(fmter, "The child process died partway through a frame"),
+            ErrorKind::MalformedDatagram => write!(fmter, "The datagram does not hold exactly one whole frame"),
         }
     }
 }
 
 impl error::Error for Error {
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         None
     }
 
@@ -66,14 +113,37 @@ impl error::Error for Error {
 #[derive(Debug)]
 pub enum ErrorKind {
     BufferDoesntContainDelimiter,
+    #[deprecated(note = "a frame with another beginning boundary is reported as `UnknownBoundary`, which names it")]
     BeginningDoesntMatch,
     DelimiterDoesntMatch,
     BufferEmpty,
+    EndingDoesntMatch,
+    UnknownBoundary(String),
+    ChannelClosed(u64),
+    ChannelAlreadyOpen(u64),
+    FlowControlViolation(u64),
+    TimedOut,
+    Disconnected,
+    UnknownMethod(String),
+    Cancelled,
+    StreamFailed(String),
+    SequenceGap { expected: u64, received: u64 },
+    DuplicateSequence(u64),
+    OutOfOrderSequence(u64),
+    HashMismatch,
+    PeerTimeout,
+    Closed { reason: Option<u64> },
+    Remote { code: u64, message: String },
+    WriteTimedOut { written: usize, remaining: usize },
+    UnknownSession(u64),
+    ProcessDied { code: Option<i32> },
+    MalformedDatagram,
     NotUTF8(string::FromUtf8Error),
     IOError(io::Error),
     IntParseError(num::ParseIntError),
 }
 
+#[allow(deprecated)]
 impl PartialEq for ErrorKind {
     fn eq(&self, other: &Self) -> bool {
         let me = match *self {
@@ -85,6 +155,27 @@ impl PartialEq for ErrorKind {
             ErrorKind::BeginningDoesntMatch => 4,
             ErrorKind::DelimiterDoesntMatch => 5,
             ErrorKind::BufferEmpty => 6,
+            ErrorKind::EndingDoesntMatch => 7,
+            ErrorKind::UnknownBoundary(_) => 8,
+            ErrorKind::ChannelClosed(_) => 9,
+            ErrorKind::ChannelAlreadyOpen(_) => 10,
+            ErrorKind::FlowControlViolation(_) => 11,
+            ErrorKind::TimedOut => 12,
+            ErrorKind::Disconnected => 13,
+            ErrorKind::UnknownMethod(_) => 14,
+            ErrorKind::Cancelled => 15,
+            ErrorKind::StreamFailed(_) => 16,
+            ErrorKind::SequenceGap { .. } => 17,
+            ErrorKind::DuplicateSequence(_) => 18,
+            ErrorKind::OutOfOrderSequence(_) => 19,
+            ErrorKind::HashMismatch => 20,
+            ErrorKind::PeerTimeout => 21,
+            ErrorKind::Closed { .. } => 22,
+            ErrorKind::Remote { .. } => 23,
+            ErrorKind::WriteTimedOut { .. } => 24,
+            ErrorKind::UnknownSession(_) => 25,
+            ErrorKind::ProcessDied { .. } => 26,
+            ErrorKind::MalformedDatagram => 27,
         };
         let them = match *other {
             ErrorKind::IntParseError(_) => 0,
@@ -95,6 +186,27 @@ impl PartialEq for ErrorKind {
             ErrorKind::BeginningDoesntMatch => 4,
             ErrorKind::DelimiterDoesntMatch => 5,
             ErrorKind::BufferEmpty => 6,
+            ErrorKind::EndingDoesntMatch => 7,
+            ErrorKind::UnknownBoundary(_) => 8,
+            ErrorKind::ChannelClosed(_) => 9,
+            ErrorKind::ChannelAlreadyOpen(_) => 10,
+            ErrorKind::FlowControlViolation(_) => 11,
+            ErrorKind::TimedOut => 12,
+            ErrorKind::Disconnected => 13,
+            ErrorKind::UnknownMethod(_) => 14,
+            ErrorKind::Cancelled => 15,
+            ErrorKind::StreamFailed(_) => 16,
+            ErrorKind::SequenceGap { .. } => 17,
+            ErrorKind::DuplicateSequence(_) => 18,
+            ErrorKind::OutOfOrderSequence(_) => 19,
+            ErrorKind::HashMismatch => 20,
+            ErrorKind::PeerTimeout => 21,
+            ErrorKind::Closed { .. } => 22,
+            ErrorKind::Remote { .. } => 23,
+            ErrorKind::WriteTimedOut { .. } => 24,
+            ErrorKind::UnknownSession(_) => 25,
+            ErrorKind::ProcessDied { .. } => 26,
+            ErrorKind::MalformedDatagram => 27,
         };
         me == them
     }
@@ -0,0 +1,41 @@
+/// Limits on how much a Multiplexer buffers for the channels it receives on
+///
+/// Every channel advertises a receive window to the peer, which may only send that many message bytes before the
+/// receiving side reads them and grants more credit.
+/// A new channel starts out with a whole window of credit in each direction, so its first messages go out without
+/// waiting for a window update; both ends must therefore use the same window size.
+/// Further credit is only granted while the bytes reserved across all channels stay within `max_buffered_bytes`,
+/// and a channel the peer opens is refused if its window would not fit. Channels opened on this side are never refused,
+/// but their windows count against the limit all the same.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowControl {
+    pub(crate) window_size: usize,
+    pub(crate) max_buffered_bytes: usize,
+}
+
+impl FlowControl {
+    /// Creates a new FlowControl
+    ///
+    /// `max_buffered_bytes` is raised to `window_size` if it is smaller, so a single channel can always use a whole window.
+    pub fn new(window_size: usize, max_buffered_bytes: usize) -> FlowControl {
+        FlowControl {
+            window_size,
+            max_buffered_bytes: max_buffered_bytes.max(window_size),
+        }
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    pub fn max_buffered_bytes(&self) -> usize {
+        self.max_buffered_bytes
+    }
+}
+
+impl Default for FlowControl {
+    /// A 64 KiB window per channel and at most 1 MiB buffered overall
+    fn default() -> FlowControl {
+        FlowControl::new(64 * 1024, 1024 * 1024)
+    }
+}
@@ -0,0 +1,115 @@
+use sha3::{Digest, Sha3_256};
+
+use super::sequence::SequenceTracker;
+use super::{Error, ErrorKind, Result, StreamConfiguration};
+
+/// The length of a SHA3-256 digest written as hex
+const DIGEST_HEX_SIZE: usize = 64;
+
+/// Prefixes a message with numeric header fields, each followed by the delimiter
+///
+/// Layers built on top of plain frames carry their header this way,
+/// so the frame itself keeps the format every reader understands.
+pub(crate) fn prepend_header_fields(delimiter_string: &str, fields: &[u64], message: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for field in fields {
+        payload.extend_from_slice(field.to_string().as_bytes());
+        payload.extend_from_slice(delimiter_string.as_bytes());
+    }
+    payload.extend_from_slice(message);
+    payload
+}
+
+/// Splits `count` numeric header fields off the front of a payload, returning them with the remaining message
+pub(crate) fn split_header_fields(delimiter_string: &str, count: usize, mut payload: Vec<u8>) -> Result<(Vec<u64>, Vec<u8>)> {
+    let delimiter = delimiter_string.as_bytes();
+    let mut fields = Vec::with_capacity(count);
+    for _ in 0..count {
+        let field_size = match payload.windows(delimiter.len()).position(|window| window == delimiter) {
+            Some(v) => v,
+            None => return Err(Error::from(ErrorKind::BufferDoesntContainDelimiter)),
+        };
+        let remainder = payload.split_off(field_size + delimiter.len());
+        payload.truncate(field_size);
+        fields.push(str::parse::<u64>(&String::from_utf8(payload)?)?);
+        payload = remainder;
+    }
+    Ok((fields, payload))
+}
+
+/// Prefixes a message with the hex SHA3-256 digest of its bytes, followed by the delimiter
+pub(crate) fn prepend_digest(delimiter_string: &str, message: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{:x}", Sha3_256::digest(message)).into_bytes();
+    payload.extend_from_slice(delimiter_string.as_bytes());
+    payload.extend_from_slice(message);
+    payload
+}
+
+/// Splits the digest off the front of a payload, returning the message if it matches
+///
+/// # Errors
+/// This method will return `HashMismatch` if the digest is missing or does not match the message.
+pub(crate) fn split_digest(delimiter_string: &str, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+    let digest_size = DIGEST_HEX_SIZE + delimiter_string.len();
+    if payload.len() < digest_size || &payload[DIGEST_HEX_SIZE..digest_size] != delimiter_string.as_bytes() {
+        return Err(Error::from(ErrorKind::HashMismatch));
+    }
+    let message = payload.split_off(digest_size);
+    if payload[..DIGEST_HEX_SIZE] != *format!("{:x}", Sha3_256::digest(&message)).as_bytes() {
+        return Err(Error::from(ErrorKind::HashMismatch));
+    }
+    Ok(message)
+}
+
+/// Adds the headers a configuration asks for to a message written between its boundaries
+///
+/// The sequence number comes first, so the digest covers it as well as the message.
+pub(crate) fn encode_message(configuration: &StreamConfiguration, next_sequence: &mut u64, message: &[u8]) -> Vec<u8> {
+    let delimiter_string = &configuration.delimiter_string;
+    let mut payload = Vec::from(message);
+    if configuration.sequence_numbers_enabled {
+        payload = prepend_header_fields(delimiter_string, &[*next_sequence], &payload);
+        *next_sequence += 1;
+    }
+    if configuration.hashing_enabled {
+        payload = prepend_digest(delimiter_string, &payload);
+    }
+    payload
+}
+
+/// Checks and removes the headers a configuration asks for from a message read between its boundaries
+pub(crate) fn decode_message(configuration: &StreamConfiguration, sequence: &mut SequenceTracker, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+    if configuration.hashing_enabled {
+        payload = split_digest(&configuration.delimiter_string, payload)?;
+    }
+    if configuration.sequence_numbers_enabled {
+        payload = sequence.accept(&configuration.delimiter_string, payload)?;
+    }
+    Ok(payload)
+}
+
+/// Builds the payload of an error frame, which carries the code as a header field ahead of the message
+pub(crate) fn encode_remote_error(delimiter_string: &str, code: u64, message: &str) -> Vec<u8> {
+    prepend_header_fields(delimiter_string, &[code], message.as_bytes())
+}
+
+/// Turns the payload of an error frame into the `Remote` error it carries
+///
+/// An error frame that cannot be decoded produces the local decoding error instead.
+pub(crate) fn decode_remote_error(delimiter_string: &str, payload: Vec<u8>) -> Error {
+    match split_header_fields(delimiter_string, 1, payload) {
+        Ok((fields, message)) => Error::from(ErrorKind::Remote {
+            code: fields[0],
+            message: String::from_utf8_lossy(&message).into_owned(),
+        }),
+        Err(e) => e,
+    }
+}
+
+/// Reads the optional reason code out of the payload of a close frame
+pub(crate) fn decode_close_reason(delimiter_string: &str, payload: Vec<u8>) -> Result<Option<u64>> {
+    if payload.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(split_header_fields(delimiter_string, 1, payload)?.0[0]))
+}
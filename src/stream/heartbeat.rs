@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+/// Settings for the heartbeats a DualMessenger exchanges while it reads
+///
+/// A ping is sent every `interval`, and the peer is considered dead once nothing at all has been heard from it for `timeout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heartbeat {
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+}
+
+impl Heartbeat {
+    /// Creates a new Heartbeat
+    ///
+    /// `timeout` is raised to `interval` if it is smaller, so the peer always has a chance to answer a ping.
+    pub fn new(interval: Duration, timeout: Duration) -> Heartbeat {
+        Heartbeat {
+            interval,
+            timeout: timeout.max(interval),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl Default for Heartbeat {
+    /// A ping every 5 seconds, giving up on the peer after 15
+    fn default() -> Heartbeat {
+        Heartbeat::new(Duration::from_secs(5), Duration::from_secs(15))
+    }
+}
+
+/// When pings were sent and when the peer was last heard from
+#[derive(Debug)]
+pub(crate) struct HeartbeatState {
+    heartbeat: Heartbeat,
+    last_heard: Instant,
+    // the token and send time of the ping being timed, until its pong arrives
+    outstanding_ping: Option<(u64, Instant)>,
+    last_ping: Option<Instant>,
+    next_token: u64,
+    round_trip_time: Option<Duration>,
+}
+
+impl HeartbeatState {
+    pub(crate) fn new(heartbeat: Heartbeat) -> HeartbeatState {
+        HeartbeatState {
+            heartbeat,
+            last_heard: Instant::now(),
+            outstanding_ping: None,
+            last_ping: None,
+            next_token: 0,
+            round_trip_time: None,
+        }
+    }
+
+    pub(crate) fn peer_timed_out(&self) -> bool {
+        self.last_heard.elapsed() >= self.heartbeat.timeout
+    }
+
+    /// Returns the token of a new ping if one is due, counting it as sent
+    pub(crate) fn ping_due(&mut self) -> Option<u64> {
+        if self.last_ping.is_some_and(|sent_at| sent_at.elapsed() < self.heartbeat.interval) {
+            return None;
+        }
+        let token = self.next_token;
+        let now = Instant::now();
+        self.next_token += 1;
+        self.last_ping = Some(now);
+        // the round trip is timed on one ping at a time, so a slow pong is not forgotten when the next ping goes out
+        if self.outstanding_ping.is_none() {
+            self.outstanding_ping = Some((token, now));
+        }
+        Some(token)
+    }
+
+    /// Records that a frame of any kind arrived from the peer
+    pub(crate) fn heard(&mut self) {
+        self.last_heard = Instant::now();
+    }
+
+    pub(crate) fn pong(&mut self, token: u64) {
+        if let Some((outstanding, sent_at)) = self.outstanding_ping {
+            if outstanding == token {
+                self.round_trip_time = Some(sent_at.elapsed());
+                self.outstanding_ping = None;
+            }
+        }
+    }
+
+    pub(crate) fn round_trip_time(&self) -> Option<Duration> {
+        self.round_trip_time
+    }
+}
@@ -1,86 +1,165 @@
+use std::io::{self, Read};
 use std::mem;
-use std::io::Read;
 
-use super::super::utils::{find_where_slice_begins};
-use super::{Error, Result, ErrorKind, StreamConfiguration};
+use super::{Error, Result, ErrorKind};
 
-pub fn read_message_from_reader(reader: &mut Read, configuration: &StreamConfiguration) -> Result<Vec<u8>> {
+fn create_empty_vec_of_size(size: usize) -> Vec<u8> {
+    vec![0; size]
+}
+/// The longest header or trailer we will scan for a delimiter before giving up on the frame.
+const MAX_SCANNED_SEGMENT_SIZE: usize = 1024;
 
-    let beginning_boundary = configuration.beginning_boundary.clone();
-    let ending_boundary = configuration.ending_boundary.clone();
-    let delimiter_string = configuration.delimiter_string.clone();
+/// The most we will allocate for a message on the word of its header alone; larger messages grow as they arrive.
+const MAX_PREALLOCATED_SIZE: usize = 64 * 1024;
 
-    // initalize a buffer for storing beginning delimiter
-    let mut buffer = [0; 1];
-    // the total size of the beginning of the delimiter (delimiter_string)
-    let mut delimiter_sized_vec = create_empty_vec_of_size(mem::size_of_val(delimiter_string.as_bytes()));
-    // read the beginning from the reader
-    let res = reader.read(delimiter_sized_vec.as_mut_slice());
-
-    match res {
-        Ok(v) => {
-            if v <= 0 {
-                return Err(Error::from(ErrorKind::BufferEmpty));
-            }
-        }
-        Err(e) => {
-            return Err(Error::from(e));
+/// Returns whether a header could belong to either beginning boundary, one being the other followed by digits
+///
+/// The length follows the beginning boundary directly, so `msg` with a length of 15 reads the same as `msg1` with a length of 5.
+pub(crate) fn boundaries_are_ambiguous(first: &str, second: &str) -> bool {
+    let (shorter, longer) = if first.len() <= second.len() { (first, second) } else { (second, first) };
+    longer.len() > shorter.len() && longer.starts_with(shorter) && longer[shorter.len()..].bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Reads the next message whose beginning boundary is any of the given `(beginning, ending)` pairs.
+///
+/// Returns the index of the matching pair along with the message.
+/// When several beginning boundaries match the header, the longest one wins.
+/// A well-formed frame with a boundary outside of `boundaries` is consumed and reported as `UnknownBoundary`,
+/// so the following read starts on the next frame.
+pub fn read_tagged_message_from_reader(reader: &mut dyn Read, delimiter_string: &str, boundaries: &[(&str, &str)]) -> Result<(usize, Vec<u8>)> {
+    let delimiter = delimiter_string.as_bytes();
+
+    // read the beginning delimiter, reporting an empty buffer if nothing is left
+    let mut delimiter_sized_vec = create_empty_vec_of_size(delimiter.len());
+    if !delimiter.is_empty() {
+        if read_first_byte(reader, &mut delimiter_sized_vec[..1])? == 0 {
+            return Err(Error::from(ErrorKind::BufferEmpty));
         }
+        reader.read_exact(&mut delimiter_sized_vec[1..])?;
+    }
+    if delimiter_sized_vec.as_slice() != delimiter {
+        return Err(Error::from(ErrorKind::DelimiterDoesntMatch));
     }
 
-    // if the delimiter is equal to the string
-    if delimiter_sized_vec.as_slice() == delimiter_string.as_bytes() {
-        // read the beginning boundary from the reader
-        let mut beg_bound_sized_vec = create_empty_vec_of_size(mem::size_of_val(beginning_boundary.as_bytes()));
-        let _ = reader.read_exact(beg_bound_sized_vec.as_mut_slice())?;
-        // if it matches
-        if beg_bound_sized_vec.as_slice() == beginning_boundary.as_bytes() {
-            // start scanning for the byte size
-            let mut acc_buff = Vec::new();
-            // while we haven't found delimiter_string
-            loop {
-                // read one byte at a time
-                reader.read_exact(&mut buffer)?;
-                // push the byte to the acc_buffer
-                acc_buff.push(buffer[0]);
-                // if the acc_buffer ends with the delimiter string
-                if acc_buff.ends_with(delimiter_string.as_bytes()) {
-                    // we have found the delimiter, end the loop
-                    break;
-                }
-            }
-            // find where the delimiter slice begins
-            if let Some(v) = find_where_slice_begins(&acc_buff, delimiter_string.as_bytes()) {
-                // split the number off
-                acc_buff.split_off(v);
-                // convert the string to a number
-                let buf_str = String::from_utf8(acc_buff.clone())?;
-                let num = str::parse::<usize>(&buf_str)?;
-                // create the message vector
-                let mut message_vec = create_empty_vec_of_size(num);
-                // this is the message, read the remainder                        
-                reader.read_exact(message_vec.as_mut_slice())?;
-
-                // trash the remaining message
-                let remaining_bytes = (mem::size_of_val(delimiter_string.as_bytes()) * 2) + mem::size_of_val(ending_boundary.as_bytes());
-                let mut trash_vec = create_empty_vec_of_size(remaining_bytes);
-                reader.read_exact(trash_vec.as_mut_slice())?;
-                return Ok(message_vec);
-            } else {
-                return Err(Error::from(ErrorKind::BufferDoesntContainDelimiter));
+    // the header is the beginning boundary immediately followed by the message length
+    let header = read_until_delimiter(reader, delimiter)?;
+    let matched = boundaries.iter()
+        .enumerate()
+        .filter(|&(_, &(beginning, _))| header.starts_with(beginning.as_bytes()) && is_message_length(&header[beginning.len()..]))
+        .max_by_key(|&(_, &(beginning, _))| beginning.len())
+        .map(|(index, _)| index);
+    let boundary_size = match matched {
+        Some(index) => boundaries[index].0.len(),
+        None => header.iter().rposition(|byte| !byte.is_ascii_digit()).map_or(0, |index| index + 1),
+    };
+
+    let num = str::parse::<usize>(&String::from_utf8(header[boundary_size..].to_vec())?)?;
+    let message_vec = read_message_body(reader, num)?;
+
+    // the trailer is the delimiter, the ending boundary and a final delimiter
+    reader.read_exact(delimiter_sized_vec.as_mut_slice())?;
+    if delimiter_sized_vec.as_slice() != delimiter {
+        return Err(Error::from(ErrorKind::DelimiterDoesntMatch));
+    }
+    let ending = read_until_delimiter(reader, delimiter)?;
+
+    match matched {
+        Some(index) if ending.as_slice() == boundaries[index].1.as_bytes() => Ok((index, message_vec)),
+        Some(_) => Err(Error::from(ErrorKind::EndingDoesntMatch)),
+        None => Err(Error::from(ErrorKind::UnknownBoundary(String::from_utf8_lossy(&header[..boundary_size]).into_owned()))),
+    }
+}
+
+/// Reads a message of `size` bytes, so a header claiming far more than the stream holds cannot exhaust memory
+fn read_message_body(reader: &mut dyn Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut message = Vec::with_capacity(size.min(MAX_PREALLOCATED_SIZE));
+    reader.take(size as u64).read_to_end(&mut message)?;
+    if message.len() != size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(message)
+}
+
+/// The bytes of a frame whose read was cut short by a read timeout
+///
+/// They are replayed ahead of the stream on the next read, so the frame resumes where it stopped.
+#[derive(Debug, Default)]
+pub(crate) struct PartialFrame {
+    bytes: Vec<u8>,
+}
+
+impl PartialFrame {
+    /// Reads the next tagged message like `read_tagged_message_from_reader`, keeping what was read if it times out
+    pub(crate) fn read_tagged_message(&mut self, reader: &mut dyn Read, delimiter_string: &str, boundaries: &[(&str, &str)]) -> Result<(usize, Vec<u8>)> {
+        let mut replay = ReplayReader {
+            pending: mem::take(&mut self.bytes),
+            position: 0,
+            recorded: Vec::new(),
+            reader,
+        };
+        let result = read_tagged_message_from_reader(&mut replay, delimiter_string, boundaries);
+        if let Err(ref e) = result {
+            if e.is_read_timeout() {
+                self.bytes = replay.recorded;
+                self.bytes.extend_from_slice(&replay.pending[replay.position..]);
             }
+        }
+        result
+    }
+}
+
+/// Serves the pending bytes before the stream, recording everything it hands out
+struct ReplayReader<'a> {
+    pending: Vec<u8>,
+    position: usize,
+    recorded: Vec<u8>,
+    reader: &'a mut dyn Read,
+}
+
+impl<'a> Read for ReplayReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = if self.position < self.pending.len() {
+            let count = buf.len().min(self.pending.len() - self.position);
+            buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+            self.position += count;
+            count
         } else {
-            return Err(Error::from(ErrorKind::BeginningDoesntMatch));
+            self.reader.read(buf)?
+        };
+        self.recorded.extend_from_slice(&buf[..count]);
+        Ok(count)
+    }
+}
+
+/// Reads the first byte of a frame, retrying reads that were interrupted like `read_exact` does
+fn read_first_byte(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            result => return result,
         }
-    } else {
-        return Err(Error::from(ErrorKind::DelimiterDoesntMatch));
     }
 }
 
-fn create_empty_vec_of_size(size: usize) -> Vec<u8> {
-    let mut vec = Vec::new();
-    for _ in 0..size {
-        vec.push(0);
+/// Reads one byte at a time until the delimiter is found, returning everything before it.
+fn read_until_delimiter(reader: &mut dyn Read, delimiter: &[u8]) -> Result<Vec<u8>> {
+    let mut buffer = [0; 1];
+    let mut acc_buff = Vec::new();
+    loop {
+        if acc_buff.len() > MAX_SCANNED_SEGMENT_SIZE {
+            return Err(Error::from(ErrorKind::BufferDoesntContainDelimiter));
+        }
+        reader.read_exact(&mut buffer)?;
+        acc_buff.push(buffer[0]);
+        if acc_buff.ends_with(delimiter) {
+            break;
+        }
     }
-    return vec;
-}
\ No newline at end of file
+    let segment_size = acc_buff.len() - delimiter.len();
+    acc_buff.truncate(segment_size);
+    Ok(acc_buff)
+}
+
+fn is_message_length(bytes: &[u8]) -> bool {
+    !bytes.is_empty() && bytes.iter().all(|byte| byte.is_ascii_digit())
+}
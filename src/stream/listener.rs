@@ -0,0 +1,191 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{DualMessenger, StreamConfiguration};
+
+/// How long `serve` sleeps when there is nothing to accept or no room for another connection
+const SERVE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Listeners that hand out connected streams
+pub trait Listener {
+    type Stream: Read + Write;
+
+    /// Waits for the next connection, returning its stream in blocking mode
+    fn accept_stream(&self) -> io::Result<Self::Stream>;
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept_stream(&self) -> io::Result<TcpStream> {
+        let (stream, _) = self.accept()?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn accept_stream(&self) -> io::Result<UnixStream> {
+        let (stream, _) = self.accept()?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixListener::set_nonblocking(self, nonblocking)
+    }
+}
+
+/// Asks `MessageListener::serve` to stop; clones all share the request
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    /// Stops `serve` from accepting; it returns once the connections it already has are done
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// Accepts connections and hands each one out as a DualMessenger with the same configuration
+#[derive(Debug)]
+pub struct MessageListener<L> where L: Listener {
+    configuration: StreamConfiguration,
+    listener: L,
+}
+
+impl MessageListener<TcpListener> {
+
+    /// Binds a TCP listener to the given address
+    ///
+    /// # Errors
+    /// This method will return Err if the address cannot be bound.
+    pub fn bind_tcp<A: ToSocketAddrs>(address: A, config: StreamConfiguration) -> io::Result<MessageListener<TcpListener>> {
+        Ok(MessageListener::new(TcpListener::bind(address)?, config))
+    }
+}
+
+#[cfg(unix)]
+impl MessageListener<UnixListener> {
+
+    /// Binds a Unix socket listener to the given path
+    ///
+    /// # Errors
+    /// This method will return Err if the path cannot be bound, such as when a socket file is already there.
+    pub fn bind_unix<P: AsRef<Path>>(path: P, config: StreamConfiguration) -> io::Result<MessageListener<UnixListener>> {
+        Ok(MessageListener::new(UnixListener::bind(path)?, config))
+    }
+}
+
+impl<L: Listener> MessageListener<L> {
+
+    /// Initializes a new MessageListener around a listener that is already bound
+    pub fn new(listener: L, config: StreamConfiguration) -> MessageListener<L> {
+        MessageListener {
+            configuration: config,
+            listener,
+        }
+    }
+
+    pub fn get_listener(&self) -> &L {
+        &self.listener
+    }
+
+    /// Waits for the next connection and wraps it in a DualMessenger
+    pub fn accept(&self) -> io::Result<DualMessenger<L::Stream>> {
+        let stream = self.listener.accept_stream()?;
+        Ok(DualMessenger::new_from_config(self.configuration.clone(), stream))
+    }
+
+    /// Returns an iterator that accepts connections forever
+    pub fn incoming(&self) -> Incoming<'_, L> {
+        Incoming { listener: self }
+    }
+
+    /// Accepts connections and runs `handler` on its own thread for each one
+    ///
+    /// No more than `max_connections` handlers run at once; further connections wait to be accepted until one finishes.
+    /// Once `shutdown` is requested, no more connections are accepted, and this returns when every running handler has.
+    /// A handler that panics only ends its own connection.
+    ///
+    /// # Errors
+    /// This method will return Err if accepting fails for any reason other than a connection being aborted before it was
+    /// accepted. Running handlers are still waited for first.
+    pub fn serve<F>(&self, max_connections: usize, shutdown: &Shutdown, handler: F) -> io::Result<()>
+        where F: Fn(DualMessenger<L::Stream>) + Send + Sync + 'static, L::Stream: Send + 'static {
+        let handler = Arc::new(handler);
+        let max_connections = max_connections.max(1);
+        let mut connections: Vec<JoinHandle<()>> = Vec::new();
+
+        // the listener is polled so that a shutdown is noticed without another connection arriving
+        self.listener.set_nonblocking(true)?;
+        let result = loop {
+            if shutdown.is_shutdown() {
+                break Ok(());
+            }
+            connections.retain(|connection| !connection.is_finished());
+            if connections.len() >= max_connections {
+                thread::sleep(SERVE_POLL_INTERVAL);
+                continue;
+            }
+            match self.accept() {
+                Ok(messenger) => {
+                    let handler = handler.clone();
+                    connections.push(thread::spawn(move || handler(messenger)));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(SERVE_POLL_INTERVAL),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted || e.kind() == io::ErrorKind::ConnectionAborted => {}
+                Err(e) => break Err(e),
+            }
+        };
+
+        for connection in connections {
+            // a panicking handler has already ended its connection, which is all it can affect
+            let _ = connection.join();
+        }
+        self.listener.set_nonblocking(false)?;
+        result
+    }
+}
+
+/// An iterator over the connections a MessageListener accepts
+///
+/// See `MessageListener::incoming`.
+#[derive(Debug)]
+pub struct Incoming<'a, L: 'a> where L: Listener {
+    listener: &'a MessageListener<L>,
+}
+
+impl<'a, L: Listener> Iterator for Incoming<'a, L> {
+    type Item = io::Result<DualMessenger<L::Stream>>;
+
+    fn next(&mut self) -> Option<io::Result<DualMessenger<L::Stream>>> {
+        Some(self.listener.accept())
+    }
+}
@@ -0,0 +1,227 @@
+//! In-memory streams for tests and for messengers talking within one process
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use super::{ReadTimeout, TryCloneStream, WriteTimeout};
+
+/// One direction of a duplex, along with the settings of the endpoints at either end of it
+#[derive(Debug, Default)]
+struct PipeState {
+    buffer: VecDeque<u8>,
+    capacity: Option<usize>,
+    writer_dropped: bool,
+    reader_dropped: bool,
+    reader_nonblocking: bool,
+    writer_nonblocking: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    changed: Condvar,
+}
+
+impl Pipe {
+    fn new(capacity: Option<usize>) -> Arc<Pipe> {
+        Arc::new(Pipe {
+            state: Mutex::new(PipeState {
+                capacity,
+                ..PipeState::default()
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits for the pipe to change, returning None once `timeout` has passed since `started`
+    fn wait<'a>(&self, state: MutexGuard<'a, PipeState>, started: Instant, timeout: Option<Duration>) -> Option<MutexGuard<'a, PipeState>> {
+        match timeout {
+            None => Some(self.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner())),
+            Some(timeout) => {
+                let remaining = timeout.checked_sub(started.elapsed())?;
+                let (state, _) = self.changed.wait_timeout(state, remaining).unwrap_or_else(|poisoned| poisoned.into_inner());
+                Some(state)
+            }
+        }
+    }
+}
+
+/// One end of an in-memory stream
+///
+/// Reads block until the other end writes, and return 0 once it has been dropped with nothing left to read.
+/// In non-blocking mode, reads and writes that would wait return `WouldBlock` instead, and a read or write timeout
+/// turns a wait that runs too long into `WouldBlock` as well, like a socket's.
+/// Handles made with `try_clone_stream` share the end, which counts as dropped once the last of them is.
+#[derive(Debug)]
+pub struct MemoryStream {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    handles: Arc<AtomicUsize>,
+}
+
+/// Returns two connected ends of an in-memory stream, with no limit on what either buffers
+pub fn duplex() -> (MemoryStream, MemoryStream) {
+    connect(None)
+}
+
+/// Returns two connected ends of an in-memory stream, each buffering at most `capacity` bytes sent to it
+///
+/// Writes beyond the capacity wait for the other end to read, so a slow reader holds up the writer like a socket's would.
+/// `capacity` is raised to 1 if it is smaller.
+pub fn duplex_with_capacity(capacity: usize) -> (MemoryStream, MemoryStream) {
+    connect(Some(capacity.max(1)))
+}
+
+/// Returns a single in-memory stream that reads back whatever was written to it
+///
+/// As nothing else can write to it, reading it empty returns 0 rather than waiting.
+pub fn loopback() -> MemoryStream {
+    let pipe = Pipe::new(None);
+    MemoryStream::new(pipe.clone(), pipe)
+}
+
+fn connect(capacity: Option<usize>) -> (MemoryStream, MemoryStream) {
+    let (left, right) = (Pipe::new(capacity), Pipe::new(capacity));
+    (MemoryStream::new(left.clone(), right.clone()), MemoryStream::new(right, left))
+}
+
+fn would_block() -> io::Error {
+    io::Error::from(io::ErrorKind::WouldBlock)
+}
+
+impl MemoryStream {
+
+    fn new(incoming: Arc<Pipe>, outgoing: Arc<Pipe>) -> MemoryStream {
+        MemoryStream {
+            incoming,
+            outgoing,
+            handles: Arc::new(AtomicUsize::new(1)),
+        }
+    }
+
+    /// Switches reads and writes between waiting and returning `WouldBlock`
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.incoming.lock().reader_nonblocking = nonblocking;
+        self.outgoing.lock().writer_nonblocking = nonblocking;
+    }
+
+    /// Returns how many bytes are waiting to be read from this end
+    pub fn available(&self) -> usize {
+        self.incoming.lock().buffer.len()
+    }
+
+    fn is_loopback(&self) -> bool {
+        Arc::ptr_eq(&self.incoming, &self.outgoing)
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let started = Instant::now();
+        let mut state = self.incoming.lock();
+        while state.buffer.is_empty() {
+            if state.writer_dropped || self.is_loopback() {
+                return Ok(0);
+            }
+            if state.reader_nonblocking {
+                return Err(would_block());
+            }
+            let timeout = state.read_timeout;
+            state = self.incoming.wait(state, started, timeout).ok_or_else(would_block)?;
+        }
+        let count = buf.len().min(state.buffer.len());
+        for (byte, value) in buf.iter_mut().zip(state.buffer.drain(..count)) {
+            *byte = value;
+        }
+        self.incoming.changed.notify_all();
+        Ok(count)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let started = Instant::now();
+        let mut state = self.outgoing.lock();
+        loop {
+            if state.reader_dropped {
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+            }
+            let room = state.capacity.map_or(buf.len(), |capacity| capacity.saturating_sub(state.buffer.len()));
+            if room > 0 {
+                let count = room.min(buf.len());
+                state.buffer.extend(&buf[..count]);
+                self.outgoing.changed.notify_all();
+                return Ok(count);
+            }
+            if state.writer_nonblocking {
+                return Err(would_block());
+            }
+            let timeout = state.write_timeout;
+            state = self.outgoing.wait(state, started, timeout).ok_or_else(would_block)?;
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ReadTimeout for MemoryStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.incoming.lock().read_timeout)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.incoming.lock().read_timeout = timeout;
+        Ok(())
+    }
+}
+
+impl WriteTimeout for MemoryStream {
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.outgoing.lock().write_timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.outgoing.lock().write_timeout = timeout;
+        Ok(())
+    }
+}
+
+impl TryCloneStream for MemoryStream {
+    fn try_clone_stream(&self) -> io::Result<MemoryStream> {
+        self.handles.fetch_add(1, Ordering::SeqCst);
+        Ok(MemoryStream {
+            incoming: self.incoming.clone(),
+            outgoing: self.outgoing.clone(),
+            handles: self.handles.clone(),
+        })
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        if self.handles.fetch_sub(1, Ordering::SeqCst) > 1 {
+            return;
+        }
+        self.incoming.lock().reader_dropped = true;
+        self.incoming.changed.notify_all();
+        self.outgoing.lock().writer_dropped = true;
+        self.outgoing.changed.notify_all();
+    }
+}
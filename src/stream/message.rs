@@ -0,0 +1,22 @@
+use super::Result;
+
+/// A message type whose variants are told apart by their beginning boundary
+///
+/// Every variant is written between its own pair of boundaries, so the beginning boundary acts as the message-type tag.
+/// This is usually implemented through `#[derive(Message)]` from the `messenger_plus_derive` crate.
+pub trait Message: Sized {
+    /// Returns every `(beginning, ending)` boundary pair this type can be read from
+    fn boundaries() -> &'static [(&'static str, &'static str)];
+
+    /// Returns the `(beginning, ending)` boundary pair this value is written between
+    fn boundary(&self) -> (&'static str, &'static str);
+
+    /// Returns the bytes written between the boundaries
+    fn payload(&self) -> &[u8];
+
+    /// Rebuilds a value from the beginning boundary it was read with and its payload
+    ///
+    /// # Errors
+    /// This method will return `UnknownBoundary` if no variant uses `beginning_boundary`.
+    fn from_frame(beginning_boundary: &str, payload: Vec<u8>) -> Result<Self>;
+}
@@ -4,6 +4,28 @@ mod dual_stream;
 mod stream_configuration;
 mod internal_reading_code;
 mod error;
+mod message;
+mod multi_read_stream;
+mod dispatcher;
+mod frame_header;
+mod sequence;
+mod multiplexer;
+mod flow_control;
+mod rpc;
+mod reliable;
+mod replay;
+mod heartbeat;
+mod split;
+mod shared_writer;
+mod bridge;
+mod timeout;
+mod listener;
+mod reconnect;
+mod session;
+mod process;
+mod stdio;
+mod datagram;
+pub mod memory;
 
 pub use self::read_stream::*;
 pub use self::write_stream::*;
@@ -11,4 +33,22 @@ pub use self::dual_stream::*;
 pub use self::stream_configuration::*;
 #[doc(hidden)]
 pub(crate) use self::internal_reading_code::*;
-pub use self::error::*;
\ No newline at end of file
+pub use self::error::*;
+pub use self::message::*;
+pub use self::multi_read_stream::*;
+pub use self::dispatcher::*;
+pub use self::multiplexer::*;
+pub use self::flow_control::*;
+pub use self::rpc::*;
+pub use self::reliable::*;
+pub use self::heartbeat::Heartbeat;
+pub use self::split::{ReadHalf, WriteHalf, TryCloneStream};
+pub use self::shared_writer::*;
+pub use self::bridge::ReaderThread;
+pub use self::timeout::{ReadTimeout, WriteTimeout};
+pub use self::listener::*;
+pub use self::reconnect::*;
+pub use self::session::*;
+pub use self::process::*;
+pub use self::stdio::*;
+pub use self::datagram::*;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::io::Read;
+use super::{boundaries_are_ambiguous, read_tagged_message_from_reader, Result};
+
+/// A message read by a MultiMessageReader along with the boundaries it was found between
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedMessage {
+    pub beginning_boundary: String,
+    pub ending_boundary: String,
+    pub message: Vec<u8>,
+}
+
+pub struct MultiMessageReader<T> where T: Read {
+    delimiter_string: String,
+    boundaries: Vec<(String, String)>,
+    reader: T,
+}
+
+impl<T: Read> MultiMessageReader<T> {
+
+    /// Initializes a new MultiMessageReader
+    ///
+    /// MultiMessageReaders read a given `Read` trait-object for messages between any of the given boundary pairs.
+    ///
+    /// # Panics
+    /// Panics if one beginning boundary is another followed by digits, such as `msg` and `msg1`,
+    /// as the length written after a boundary could not tell their frames apart.
+    pub fn new<V: Into<String>>(delimiter_string: V, boundaries: Vec<(V, V)>, reader: T) -> MultiMessageReader<T> {
+        let mut multi_reader = MultiMessageReader {
+            delimiter_string: delimiter_string.into(),
+            boundaries: Vec::new(),
+            reader,
+        };
+        for (beg_bound, end_bound) in boundaries {
+            multi_reader.add_boundaries(beg_bound, end_bound);
+        }
+        multi_reader
+    }
+
+    /// Accepts messages between another pair of boundaries
+    ///
+    /// # Panics
+    /// Panics if the beginning boundary and an accepted one are the same but for digits at the end. See `new`.
+    pub fn add_boundaries<V: Into<String>>(&mut self, beg_bound: V, end_bound: V) {
+        let beg_bound = beg_bound.into();
+        if let Some((other, _)) = self.boundaries.iter().find(|(other, _)| boundaries_are_ambiguous(other, &beg_bound)) {
+            panic!("the beginning boundaries {:?} and {:?} cannot be told apart", other, beg_bound);
+        }
+        self.boundaries.push((beg_bound, end_bound.into()));
+    }
+
+    pub fn boundaries(&self) -> &[(String, String)] {
+        &self.boundaries
+    }
+
+    pub(crate) fn boundaries_mut(&mut self) -> &mut Vec<(String, String)> {
+        &mut self.boundaries
+    }
+
+    pub fn get_reader(&self) -> &T {
+        &self.reader
+    }
+
+    /// Reads the next message between any of the accepted boundary pairs
+    ///
+    /// When several beginning boundaries match a frame, the longest one is used.
+    /// Payloads are returned as they were framed; a digest or sequence number written with them is not checked.
+    ///
+    /// # Errors
+    /// This method will return `BufferEmpty` if the stream ends before a new message begins.
+    /// This method will return `UnknownBoundary` if a frame uses a beginning boundary that was not accepted.
+    /// The unknown frame is still consumed, so the stream stays in sync and the next read starts on the following frame.
+    /// This method will return `EndingDoesntMatch` if a frame ends with a different boundary than the one paired with its beginning.
+    pub fn read_next_message(&mut self) -> Result<TaggedMessage> {
+        let boundaries: Vec<(&str, &str)> = self.boundaries.iter().map(|(beg, end)| (beg.as_str(), end.as_str())).collect();
+        let (index, message) = read_tagged_message_from_reader(&mut self.reader, &self.delimiter_string, &boundaries)?;
+        let (ref beginning_boundary, ref ending_boundary) = self.boundaries[index];
+        Ok(TaggedMessage {
+            beginning_boundary: beginning_boundary.clone(),
+            ending_boundary: ending_boundary.clone(),
+            message,
+        })
+    }
+}
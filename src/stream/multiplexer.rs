@@ -0,0 +1,567 @@
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use super::stream_configuration::StreamConfiguration;
+use super::flow_control::FlowControl;
+use super::frame_header::{prepend_header_fields, split_header_fields};
+use super::split::{self, ReadHalf, WriteHalf, TryCloneStream};
+use super::write_stream::{build_frame, write_frame, write_pending};
+use super::{Error, ErrorKind, Result, PartialFrame};
+
+const DATA_FRAME: usize = 0;
+const OPEN_FRAME: usize = 1;
+const CLOSE_FRAME: usize = 2;
+const WINDOW_FRAME: usize = 3;
+
+struct ChannelState {
+    queue: VecDeque<Vec<u8>>,
+    queued_bytes: usize,
+    // receive credit handed to the peer that has not come back through reads yet, including queued bytes
+    granted: usize,
+    // send credit the peer has handed us
+    credit: usize,
+    peer_window: Option<usize>,
+    remote_closed: bool,
+}
+
+impl ChannelState {
+    /// Both ends start a channel as if a whole window had already been granted in each direction
+    fn new(window_size: usize) -> ChannelState {
+        ChannelState {
+            queue: VecDeque::new(),
+            queued_bytes: 0,
+            granted: window_size,
+            credit: window_size,
+            peer_window: Some(window_size),
+            remote_closed: false,
+        }
+    }
+}
+
+struct MultiplexerState {
+    channels: HashMap<u64, ChannelState>,
+    accept_queue: VecDeque<u64>,
+    // whether a thread is reading a frame from the stream, which it does with the state unlocked
+    reading: bool,
+}
+
+impl MultiplexerState {
+
+    /// Picks the channels whose receive window should be topped up, counting the increments as granted
+    ///
+    /// Credit is only handed out while the bytes reserved across all channels stay within `max_buffered_bytes`.
+    fn take_grants(&mut self, flow_control: &FlowControl) -> Vec<(u64, usize)> {
+        let window_size = flow_control.window_size;
+        let mut available = flow_control.max_buffered_bytes.saturating_sub(self.reserved());
+
+        let mut ids: Vec<u64> = self.channels.iter()
+            .filter(|&(_, state)| !state.remote_closed && (state.granted == 0 || window_size - state.granted >= window_size / 2))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+
+        let mut grants = Vec::new();
+        for id in ids {
+            let state = self.channels.get_mut(&id).expect("ids were collected from the channel map");
+            let increment = (window_size - state.granted).min(available);
+            if increment == 0 {
+                continue;
+            }
+            state.granted += increment;
+            available -= increment;
+            grants.push((id, increment));
+        }
+        grants
+    }
+
+    /// Takes the next queued message of a channel, returning its bytes to the receive window
+    fn pop_message(&mut self, id: u64) -> Option<Vec<u8>> {
+        let state = self.channels.get_mut(&id)?;
+        let message = state.queue.pop_front()?;
+        state.queued_bytes -= message.len();
+        state.granted -= message.len();
+        Some(message)
+    }
+
+    /// Returns how many bytes of receive window are handed out across all channels
+    fn reserved(&self) -> usize {
+        self.channels.values().map(|state| state.granted).sum()
+    }
+
+    /// Files a frame read from the stream under its channel
+    ///
+    /// Returns the id of a channel the peer opened that has to be refused, as its window would not fit in `max_buffered_bytes`.
+    fn file_frame(&mut self, kind: usize, fields: &[u64], message: Vec<u8>, flow_control: &FlowControl) -> Result<Option<u64>> {
+        let id = fields[0];
+        match kind {
+            DATA_FRAME => {
+                // frames for channels we have closed, or never opened, are dropped
+                if let Some(state) = self.channels.get_mut(&id) {
+                    if !state.remote_closed {
+                        if state.queued_bytes + message.len() > state.granted {
+                            return Err(Error::from(ErrorKind::FlowControlViolation(id)));
+                        }
+                        state.queued_bytes += message.len();
+                        state.queue.push_back(message);
+                    }
+                }
+            }
+            OPEN_FRAME => {
+                if self.channels.contains_key(&id) {
+                    return Ok(None);
+                }
+                if self.reserved() + flow_control.window_size > flow_control.max_buffered_bytes {
+                    return Ok(Some(id));
+                }
+                if let Entry::Vacant(entry) = self.channels.entry(id) {
+                    entry.insert(ChannelState::new(flow_control.window_size));
+                    self.accept_queue.push_back(id);
+                }
+            }
+            WINDOW_FRAME => {
+                if let Some(state) = self.channels.get_mut(&id) {
+                    // no window the peer can honestly grant comes near overflowing the credit
+                    state.credit = match usize::try_from(fields[1]).ok().and_then(|increment| state.credit.checked_add(increment)) {
+                        Some(credit) => credit,
+                        None => return Err(Error::from(ErrorKind::FlowControlViolation(id))),
+                    };
+                    state.peer_window = Some(usize::try_from(fields[2]).unwrap_or(usize::MAX));
+                }
+            }
+            _ => {
+                if let Some(state) = self.channels.get_mut(&id) {
+                    state.remote_closed = true;
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The side of the stream frames are read from: a handle of its own, or one shared with the writing side
+enum StreamReader<T> {
+    Shared(ReadHalf<T>),
+    Cloned(T),
+}
+
+impl<T: Read> Read for StreamReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            StreamReader::Shared(ref mut half) => half.read(buf),
+            StreamReader::Cloned(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+enum StreamWriter<T> {
+    Shared(WriteHalf<T>),
+    Cloned(T),
+}
+
+impl<T: Write> Write for StreamWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            StreamWriter::Shared(ref mut half) => half.write(buf),
+            StreamWriter::Cloned(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            StreamWriter::Shared(ref mut half) => half.flush(),
+            StreamWriter::Cloned(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+struct FrameWriter<T> {
+    stream: StreamWriter<T>,
+    // the rest of a frame the stream took only part of, which goes out ahead of the next one
+    pending: Vec<u8>,
+}
+
+struct FrameReader<T> {
+    stream: StreamReader<T>,
+    partial: PartialFrame,
+}
+
+/// Everything the clones of a Multiplexer and its channels share
+///
+/// The channel state, the reading side and the writing side are locked separately,
+/// and the state is never held while waiting on the stream.
+struct Shared<T> {
+    configuration: StreamConfiguration,
+    frame_configurations: Vec<StreamConfiguration>,
+    flow_control: FlowControl,
+    state: Mutex<MultiplexerState>,
+    // signalled whenever a frame has been filed, or the reading thread gives up the stream
+    frame_filed: Condvar,
+    reader: Mutex<FrameReader<T>>,
+    writer: Mutex<FrameWriter<T>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<T: Read + Write> Shared<T> {
+
+    fn write_frame(&self, kind: usize, fields: &[u64], message: &[u8]) -> io::Result<usize> {
+        self.write_frame_to(&mut lock(&self.writer), kind, fields, message)
+    }
+
+    fn write_frame_to(&self, writer: &mut FrameWriter<T>, kind: usize, fields: &[u64], message: &[u8]) -> io::Result<usize> {
+        let FrameWriter { ref mut stream, ref mut pending } = *writer;
+        write_pending(stream, pending).1?;
+        write_frame(stream, pending, self.build_frame(kind, fields, message))
+    }
+
+    fn build_frame(&self, kind: usize, fields: &[u64], message: &[u8]) -> Vec<u8> {
+        let payload = prepend_header_fields(&self.configuration.delimiter_string, fields, message);
+        build_frame(&self.frame_configurations[kind], &payload)
+    }
+
+    /// Writes a message on a channel, giving the credit it took back if none of its frame went out
+    fn write_data(&self, id: u64, message: &[u8]) -> io::Result<usize> {
+        let mut writer = lock(&self.writer);
+        let FrameWriter { ref mut stream, ref mut pending } = *writer;
+        let (result, dropped) = match write_pending(stream, pending).1 {
+            Err(e) => (Err(e), true),
+            Ok(()) => {
+                let result = write_frame(stream, pending, self.build_frame(DATA_FRAME, &[id], message));
+                (result, pending.is_empty())
+            }
+        };
+        if result.is_err() && dropped {
+            if let Some(state) = lock(&self.state).channels.get_mut(&id) {
+                state.credit += message.len();
+            }
+        }
+        result
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let mut writer = lock(&self.writer);
+        let FrameWriter { ref mut stream, ref mut pending } = *writer;
+        write_pending(stream, pending).1?;
+        stream.flush()
+    }
+
+    /// Tops up the receive window of every channel that has used at least half of it
+    fn grant_credit(&self) -> io::Result<()> {
+        let grants = lock(&self.state).take_grants(&self.flow_control);
+        if grants.is_empty() {
+            return Ok(());
+        }
+        let window_size = self.flow_control.window_size as u64;
+        for (id, increment) in grants {
+            self.write_frame(WINDOW_FRAME, &[id, increment as u64, window_size], &[])?;
+        }
+        self.flush()
+    }
+
+    /// Reads one frame from the underlying stream, returning its kind, header fields and message
+    fn read_frame(&self) -> Result<(usize, Vec<u64>, Vec<u8>)> {
+        let boundaries: Vec<(&str, &str)> = self.frame_configurations.iter()
+            .map(|config| (config.beginning_boundary.as_str(), config.ending_boundary.as_str()))
+            .collect();
+        let (kind, payload) = {
+            let mut reader = lock(&self.reader);
+            let FrameReader { ref mut stream, ref mut partial } = *reader;
+            partial.read_tagged_message(stream, &self.configuration.delimiter_string, &boundaries)?
+        };
+        let field_count = if kind == WINDOW_FRAME { 3 } else { 1 };
+        let (fields, message) = split_header_fields(&self.configuration.delimiter_string, field_count, payload)?;
+        Ok((kind, fields, message))
+    }
+
+    /// Waits until `ready` has an answer, reading frames from the stream in the meantime
+    ///
+    /// Only one thread reads at a time, and it does so with the state unlocked,
+    /// so the others can keep writing and taking the messages already queued for them.
+    /// The rest wait for it to file a frame, then check again; if the read fails, the error goes to the reading thread only.
+    fn wait_for<R, F>(&self, mut ready: F) -> Result<R> where F: FnMut(&mut MultiplexerState) -> Option<Result<R>> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(result) = ready(&mut state) {
+                return result;
+            }
+            if state.reading {
+                state = self.frame_filed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            }
+
+            state.reading = true;
+            drop(state);
+            let frame = self.read_frame();
+            state = lock(&self.state);
+            state.reading = false;
+            self.frame_filed.notify_all();
+
+            let (kind, fields, message) = frame?;
+            if let Some(refused) = state.file_frame(kind, &fields, message, &self.flow_control)? {
+                // the state is let go first, as the writer is always locked before it
+                drop(state);
+                self.write_frame(CLOSE_FRAME, &[refused], &[])?;
+                self.flush()?;
+                state = lock(&self.state);
+            }
+        }
+    }
+}
+
+/// Carries several independent channels of messages over one stream
+///
+/// Every frame carries the id of the channel it belongs to ahead of its message.
+/// Channels are opened and closed with control frames that use reserved boundaries.
+/// Each channel is flow controlled on its own, so a slow reader on one channel never stalls the others;
+/// see `FlowControl`. A channel the peer opens while its window would not fit in `max_buffered_bytes` is closed
+/// right away, so writing on it fails with `ChannelClosed` once the close arrives.
+/// The configuration's hashing and sequence number settings do not apply to channel messages.
+///
+/// A Multiplexer can be cloned and shared between threads; every clone drives the same stream.
+/// Whichever thread is waiting for a frame reads the stream on behalf of all of them, while the others keep writing.
+/// That needs a stream that can be read and written at the same time, so build the Multiplexer with `try_new_cloned`
+/// if the stream supports it. Otherwise reading and writing share the stream like a ReadHalf and WriteHalf,
+/// and a read that blocks holds up every write until it returns.
+pub struct Multiplexer<T> where T: Read + Write {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Multiplexer<T> where T: Read + Write {
+
+    /// Initializes a new Multiplexer
+    ///
+    /// Channel data is written between the given boundaries.
+    pub fn new<V: Into<String>>(delimiter_string: V, beg_bound: V, end_bound: V, channel: T, hashing_enabled: bool) -> Multiplexer<T> {
+        Multiplexer::new_from_config(StreamConfiguration::new(delimiter_string, beg_bound, end_bound, hashing_enabled), channel)
+    }
+
+    pub fn new_from_config(config: StreamConfiguration, channel: T) -> Multiplexer<T> {
+        Multiplexer::new_with_flow_control(config, FlowControl::default(), channel)
+    }
+
+    pub fn new_with_flow_control(config: StreamConfiguration, flow_control: FlowControl, channel: T) -> Multiplexer<T> {
+        let (read_half, write_half) = split::halves(Box::new(channel));
+        Multiplexer::from_parts(config, flow_control, StreamReader::Shared(read_half), StreamWriter::Shared(write_half))
+    }
+
+    /// Initializes a new Multiplexer that reads from a clone of the stream, so reads never hold up writes
+    ///
+    /// # Errors
+    /// This method will return an error if the stream could not be cloned.
+    pub fn try_new_cloned(config: StreamConfiguration, flow_control: FlowControl, channel: T) -> io::Result<Multiplexer<T>> where T: TryCloneStream {
+        let reader = channel.try_clone_stream()?;
+        Ok(Multiplexer::from_parts(config, flow_control, StreamReader::Cloned(reader), StreamWriter::Cloned(channel)))
+    }
+
+    fn from_parts(config: StreamConfiguration, flow_control: FlowControl, reader: StreamReader<T>, writer: StreamWriter<T>) -> Multiplexer<T> {
+        let (open_beg, open_end) = config.control_boundaries("open");
+        let (close_beg, close_end) = config.control_boundaries("close");
+        let (window_beg, window_end) = config.control_boundaries("window");
+        let frame_configurations = vec![
+            config.clone(),
+            config.with_boundaries(open_beg, open_end),
+            config.with_boundaries(close_beg, close_end),
+            config.with_boundaries(window_beg, window_end),
+        ];
+        Multiplexer {
+            shared: Arc::new(Shared {
+                configuration: config,
+                frame_configurations,
+                flow_control,
+                state: Mutex::new(MultiplexerState {
+                    channels: HashMap::new(),
+                    accept_queue: VecDeque::new(),
+                    reading: false,
+                }),
+                frame_filed: Condvar::new(),
+                reader: Mutex::new(FrameReader {
+                    stream: reader,
+                    partial: PartialFrame::default(),
+                }),
+                writer: Mutex::new(FrameWriter {
+                    stream: writer,
+                    pending: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Opens a channel and tells the peer about it
+    ///
+    /// # Errors
+    /// This method will return `ChannelAlreadyOpen` if a channel with this id is open, or was opened by the peer and is waiting to be accepted.
+    pub fn open_channel(&self, id: u64) -> Result<Channel<T>> {
+        {
+            // the writer is held until the open frame is out, so no window update for the channel can overtake it
+            let mut writer = lock(&self.shared.writer);
+            {
+                let mut state = lock(&self.shared.state);
+                if state.channels.contains_key(&id) {
+                    return Err(Error::from(ErrorKind::ChannelAlreadyOpen(id)));
+                }
+                state.channels.insert(id, ChannelState::new(self.shared.flow_control.window_size));
+            }
+            if let Err(e) = self.shared.write_frame_to(&mut writer, OPEN_FRAME, &[id], &[]) {
+                lock(&self.shared.state).channels.remove(&id);
+                return Err(Error::from(e));
+            }
+        }
+        self.shared.flush()?;
+        Ok(Channel::new(id, self.shared.clone()))
+    }
+
+    /// Waits for the peer to open a channel
+    ///
+    /// Messages for other channels that arrive in the meantime are queued for them.
+    ///
+    /// # Errors
+    /// This method will return `BufferEmpty` if the stream ends before the peer opens a channel.
+    pub fn accept_channel(&self) -> Result<Channel<T>> {
+        let id = self.shared.wait_for(|state| {
+            while let Some(id) = state.accept_queue.pop_front() {
+                if state.channels.contains_key(&id) {
+                    return Some(Ok(id));
+                }
+            }
+            None
+        })?;
+        Ok(Channel::new(id, self.shared.clone()))
+    }
+}
+
+impl<T> Clone for Multiplexer<T> where T: Read + Write {
+    fn clone(&self) -> Multiplexer<T> {
+        Multiplexer {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// One logical channel of a Multiplexer
+///
+/// Dropping a Channel closes it.
+pub struct Channel<T> where T: Read + Write {
+    id: u64,
+    shared: Arc<Shared<T>>,
+    closed: bool,
+    nonblocking: bool,
+}
+
+impl<T> Channel<T> where T: Read + Write {
+    fn new(id: u64, shared: Arc<Shared<T>>) -> Channel<T> {
+        Channel {
+            id,
+            shared,
+            closed: false,
+            nonblocking: false,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Moves writes on this channel into or out of nonblocking mode
+    ///
+    /// In nonblocking mode a write that needs more credit than the peer has granted fails with `WouldBlock`
+    /// instead of waiting for a window update.
+    /// Window updates are still picked up whenever any channel reads from the stream.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    /// Returns how many message bytes may be written before the peer grants more credit
+    pub fn send_credit(&self) -> usize {
+        lock(&self.shared.state).channels.get(&self.id).map_or(0, |state| state.credit)
+    }
+
+    /// Reads the next message sent on this channel
+    ///
+    /// Queued messages are returned first; otherwise frames are read from the stream until one arrives for this channel,
+    /// queueing the messages meant for other channels along the way.
+    /// If another thread is already reading the stream, this waits for it to file the frames instead.
+    ///
+    /// # Errors
+    /// This method will return `ChannelClosed` once the peer has closed the channel and every queued message has been read.
+    /// This method will return `BufferEmpty` if the stream ends first.
+    /// This method can hang if no new data is sent through the pipe as `Read` can block.
+    pub fn read_next_message(&mut self) -> Result<Vec<u8>> {
+        let id = self.id;
+        let message = self.shared.wait_for(|state| {
+            if let Some(message) = state.pop_message(id) {
+                return Some(Ok(message));
+            }
+            match state.channels.get(&id) {
+                Some(channel_state) if !channel_state.remote_closed => None,
+                _ => Some(Err(Error::from(ErrorKind::ChannelClosed(id)))),
+            }
+        })?;
+        // a grant that fails to write is retried with the next read
+        let _ = self.shared.grant_credit();
+        Ok(message)
+    }
+
+    /// Closes the channel and tells the peer about it
+    ///
+    /// Messages still queued for this channel are discarded.
+    pub fn close(mut self) -> Result<()> {
+        self.closed = true;
+        self.close_channel()
+    }
+
+    fn close_channel(&mut self) -> Result<()> {
+        lock(&self.shared.state).channels.remove(&self.id);
+        self.shared.write_frame(CLOSE_FRAME, &[self.id], &[])?;
+        // the closed channel's share of the buffer can go to the others
+        self.shared.grant_credit()?;
+        self.shared.flush()?;
+        Ok(())
+    }
+}
+
+impl<T> Write for Channel<T> where T: Read + Write {
+    /// Writes one message on this channel
+    ///
+    /// # Errors
+    /// This method will return `InvalidInput` if the message is larger than the peer's receive window.
+    /// Without enough send credit it waits for the peer to grant more, or fails with `WouldBlock` in nonblocking mode.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (id, nonblocking) = (self.id, self.nonblocking);
+        self.shared.wait_for(|state| {
+            match state.channels.get_mut(&id) {
+                Some(channel_state) if !channel_state.remote_closed => {
+                    if channel_state.peer_window.is_some_and(|window| buf.len() > window) {
+                        return Some(Err(Error::from(io::Error::new(io::ErrorKind::InvalidInput, "message is larger than the channel's receive window"))));
+                    }
+                    if channel_state.credit >= buf.len() {
+                        channel_state.credit -= buf.len();
+                        return Some(Ok(()));
+                    }
+                    if nonblocking {
+                        return Some(Err(Error::from(io::Error::from(io::ErrorKind::WouldBlock))));
+                    }
+                    None
+                }
+                _ => Some(Err(Error::from(ErrorKind::ChannelClosed(id)))),
+            }
+        })?;
+        self.shared.write_data(id, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.shared.flush()
+    }
+}
+
+impl<T> Drop for Channel<T> where T: Read + Write {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.close_channel();
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{Error, ErrorKind, Message, MessageReader, MessageWriter, Result, StreamConfiguration};
+
+/// How long a child whose output stopped partway through a frame is given to exit before it is reported
+const EXIT_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// Exchanges messages with a child process over its stdin and stdout
+///
+/// The child is killed when the ProcessMessenger is dropped, unless it has already exited.
+#[derive(Debug)]
+pub struct ProcessMessenger {
+    child: Child,
+    reader: MessageReader<ChildStdout>,
+    writer: Option<MessageWriter<ChildStdin>>,
+    stderr: Option<JoinHandle<()>>,
+}
+
+impl ProcessMessenger {
+
+    /// Starts `command` with its stdin and stdout piped, passing its stderr through to this process's
+    ///
+    /// # Errors
+    /// This method will return Err if the command cannot be started.
+    pub fn spawn(mut command: Command, config: StreamConfiguration) -> io::Result<ProcessMessenger> {
+        command.stderr(Stdio::inherit());
+        ProcessMessenger::start(command, config, None)
+    }
+
+    /// Starts `command` like `spawn`, handing every line it writes to stderr to `handler` on a thread of its own
+    ///
+    /// # Errors
+    /// This method will return Err if the command cannot be started.
+    pub fn spawn_with_stderr<F>(mut command: Command, config: StreamConfiguration, handler: F) -> io::Result<ProcessMessenger>
+        where F: FnMut(String) + Send + 'static {
+        command.stderr(Stdio::piped());
+        ProcessMessenger::start(command, config, Some(Box::new(handler)))
+    }
+
+    fn start(mut command: Command, config: StreamConfiguration, handler: Option<Box<dyn FnMut(String) + Send>>) -> io::Result<ProcessMessenger> {
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = match (child.stderr.take(), handler) {
+            (Some(stderr), Some(mut handler)) => Some(thread::spawn(move || {
+                for line in BufReader::new(stderr).lines() {
+                    match line {
+                        Ok(line) => handler(line),
+                        Err(_) => break,
+                    }
+                }
+            })),
+            _ => None,
+        };
+        Ok(ProcessMessenger {
+            child,
+            reader: MessageReader::new_from_config(config.clone(), stdout),
+            writer: Some(MessageWriter::new_from_config(config, stdin)),
+            stderr,
+        })
+    }
+
+    /// Returns the child's process ID
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Reads the next message the child wrote to its stdout
+    ///
+    /// # Errors
+    /// This method will return `BufferEmpty` if the child closed its stdout between frames, typically by exiting.
+    /// This method will return `ProcessDied` if the child's stdout ended partway through a frame.
+    /// Otherwise this method fails like `MessageReader::read_next_message`.
+    pub fn read_next_message(&mut self) -> Result<Vec<u8>> {
+        let result = self.reader.read_next_message();
+        self.check_died(result)
+    }
+
+    /// Reads the next message from the child as a typed message
+    ///
+    /// See `MessageReader::read_next_typed_message`.
+    pub fn read_next_typed_message<M: Message>(&mut self) -> Result<M> {
+        let result = self.reader.read_next_typed_message();
+        self.check_died(result)
+    }
+
+    /// Writes a typed message between the boundaries of its variant
+    ///
+    /// See `MessageWriter::send_message`.
+    pub fn send_message<M: Message>(&mut self, message: &M) -> io::Result<usize> {
+        self.writer()?.send_message(message)
+    }
+
+    /// Writes an error frame carrying a code and a message in place of a message
+    ///
+    /// See `MessageWriter::send_error`.
+    pub fn send_error(&mut self, code: u64, message: &str) -> io::Result<usize> {
+        self.writer()?.send_error(code, message)
+    }
+
+    /// Writes a close frame and then closes the child's stdin, so it sees the end of its input
+    ///
+    /// Reading carries on until the child closes its stdout. Closing again does nothing.
+    pub fn close(&mut self, reason: Option<u64>) -> io::Result<()> {
+        match self.writer.take() {
+            Some(mut writer) => writer.close(reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Closes the child's stdin and waits for it to exit
+    ///
+    /// The thread handling its stderr, if any, is finished as well.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.writer = None;
+        let status = self.child.wait()?;
+        if let Some(stderr) = self.stderr.take() {
+            // the handler panicking only means some stderr lines went unhandled
+            let _ = stderr.join();
+        }
+        Ok(status)
+    }
+
+    /// Returns the child's exit status if it has exited, without waiting
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    /// Kills the child and waits for it to exit
+    pub fn kill(&mut self) -> io::Result<ExitStatus> {
+        self.child.kill()?;
+        self.wait()
+    }
+
+    fn writer(&mut self) -> io::Result<&mut MessageWriter<ChildStdin>> {
+        match self.writer {
+            Some(ref mut writer) => Ok(writer),
+            None => Err(io::Error::from(Error::from(ErrorKind::Closed { reason: None }))),
+        }
+    }
+
+    /// Turns the end of the child's stdout partway through a frame into `ProcessDied`
+    fn check_died<V>(&mut self, result: Result<V>) -> Result<V> {
+        match result {
+            Err(ref e) if is_unexpected_eof(e) => {
+                let deadline = Instant::now() + EXIT_GRACE_PERIOD;
+                let code = loop {
+                    match self.child.try_wait() {
+                        Ok(Some(status)) => break status.code(),
+                        Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                        _ => break None,
+                    }
+                };
+                Err(Error::from(ErrorKind::ProcessDied { code }))
+            }
+            result => result,
+        }
+    }
+}
+
+impl Write for ProcessMessenger {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer()?.flush()
+    }
+}
+
+impl Drop for ProcessMessenger {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            // nothing can be done about a child that cannot be killed from here
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+fn is_unexpected_eof(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::IOError(ref e) => e.kind() == io::ErrorKind::UnexpectedEof,
+        _ => false,
+    }
+}
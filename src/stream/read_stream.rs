@@ -1,28 +1,25 @@
 use std::io::{Read};
+use std::time::{Duration, Instant};
 use super::stream_configuration::StreamConfiguration;
-use super::{read_message_from_reader, Result};
+use super::frame_header::{decode_message, decode_remote_error, decode_close_reason};
+use super::sequence::SequenceTracker;
+use super::timeout::DeadlineReader;
+use super::{Error, ErrorKind, Message, PartialFrame, ReadTimeout, Result};
 
-pub(crate) struct InternalMessageReader<'a, T: 'a> where T: Read {
-    internal_reader: &'a mut T,
-    configuration: &'a StreamConfiguration,
-}
-
-impl<'a, T: Read> InternalMessageReader<'a, T> {
-    pub(crate) fn new(internal_reader: &'a mut T, configuration: &'a StreamConfiguration) -> InternalMessageReader<'a, T> {
-        InternalMessageReader {
-            internal_reader: internal_reader,
-            configuration: configuration,
-        }
-    }
-
-    pub fn read_next_message(&mut self) -> Result<Vec<u8>> {
-        read_message_from_reader(self.internal_reader, self.configuration)
-    }
-}
+// indices of the frame kinds `read_next_message` reads
+const MESSAGE_FRAME: usize = 0;
+const ERROR_FRAME: usize = 1;
+const CLOSE_FRAME: usize = 2;
 
+#[derive(Debug)]
 pub struct MessageReader<T> where T: Read {
     configuration: StreamConfiguration,
     reader: T,
+    sequence: SequenceTracker,
+    peer_closed: Option<Option<u64>>,
+    partial: PartialFrame,
+    // shared with the MessageWriter made by the same `try_split`
+    split_token: Option<u64>,
 }
 
 impl<T: Read> MessageReader<T> {
@@ -36,16 +33,25 @@ impl<T: Read> MessageReader<T> {
                 delimiter_string: delimiter_string.into(),
                 beginning_boundary: beg_bound.into(),
                 ending_boundary: end_bound.into(),
-                hashing_enabled: hashing_enabled,
+                hashing_enabled,
+                sequence_numbers_enabled: false,
             },
-            reader: reader,
+            reader,
+            sequence: SequenceTracker::default(),
+            peer_closed: None,
+            partial: PartialFrame::default(),
+            split_token: None,
         }
     }
 
     pub fn new_from_config(config: StreamConfiguration, reader: T) -> MessageReader<T> {
         MessageReader {
             configuration: config,
-            reader: reader,
+            reader,
+            sequence: SequenceTracker::default(),
+            peer_closed: None,
+            partial: PartialFrame::default(),
+            split_token: None,
         }
     }
 
@@ -53,6 +59,37 @@ impl<T: Read> MessageReader<T> {
         &self.reader
     }
 
+    pub(crate) fn configuration(&self) -> &StreamConfiguration {
+        &self.configuration
+    }
+
+    pub(crate) fn from_parts(configuration: StreamConfiguration, reader: T, sequence: SequenceTracker, peer_closed: Option<Option<u64>>, partial: PartialFrame) -> MessageReader<T> {
+        MessageReader {
+            configuration,
+            reader,
+            sequence,
+            peer_closed,
+            partial,
+            split_token: None,
+        }
+    }
+
+    pub(crate) fn split_token(&self) -> Option<u64> {
+        self.split_token
+    }
+
+    pub(crate) fn set_split_token(&mut self, token: u64) {
+        self.split_token = Some(token);
+    }
+
+    pub(crate) fn into_parts(self) -> (StreamConfiguration, T, SequenceTracker, Option<Option<u64>>, PartialFrame) {
+        (self.configuration, self.reader, self.sequence, self.peer_closed, self.partial)
+    }
+
+    pub(crate) fn read_next_tagged_message(&mut self, boundaries: &[(&str, &str)]) -> Result<(usize, Vec<u8>)> {
+        self.partial.read_tagged_message(&mut self.reader, &self.configuration.delimiter_string, boundaries)
+    }
+
     /// Reads the next message from the MessageReader
     ///
     /// This method reads the next message from the previously created MessageReader
@@ -62,8 +99,87 @@ impl<T: Read> MessageReader<T> {
     /// This method will return Err if it cannot find a message and the stream ends (typically due to EOF).
     /// This method can hang if no new data is sent through the pipe as `Read` can block.
     /// This method can produce irratic results if the `boundary_start` or `boundary_end` is found within the message.
+    /// This method will return `UnknownBoundary`, naming the boundary, if a frame begins with a boundary other than the configured one;
+    /// that frame is skipped, so the next read starts on the one after it.
+    /// With sequence numbers enabled, this method will return `SequenceGap`, `DuplicateSequence` or `OutOfOrderSequence`
+    /// when a message arrives out of turn. Gapped and out of order messages are returned by the next read; duplicates are dropped.
+    /// With hashing enabled, this method will return `HashMismatch` if the message was corrupted; the message is dropped.
+    /// This method will return `Remote` when the peer sent an error frame in place of a message.
+    /// This method will return `Closed` once the peer has closed the stream, and on every read after that.
+    /// Pings and pongs from a peer with a heartbeat are skipped. They go unanswered, so a peer that sends pings to this
+    /// reader's stream will give up on it unless a DualMessenger on the same stream answers them.
+    /// A read that times out (see `read_next_message_timeout`) keeps the part of the frame it got,
+    /// and the next read carries on from there.
     pub fn read_next_message(&mut self) -> Result<Vec<u8>> {
-        let mut internal_reader = InternalMessageReader::new(&mut self.reader, &self.configuration);
-        internal_reader.read_next_message()
+        read_message(&self.configuration, &mut self.sequence, &mut self.peer_closed, &mut self.partial, &mut self.reader)
+    }
+
+    /// Reads the next message, giving up if the whole frame has not arrived within `timeout`
+    ///
+    /// See `read_next_message_deadline`.
+    pub fn read_next_message_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>> where T: ReadTimeout {
+        self.read_next_message_deadline(Instant::now() + timeout)
+    }
+
+    /// Reads the next message, giving up if the whole frame has not arrived by `deadline`
+    ///
+    /// The deadline covers every read the frame takes, not just the first one.
+    /// The stream's own read timeout is put back once this returns.
+    ///
+    /// # Errors
+    /// This method will return an `IOError` of kind `TimedOut` or `WouldBlock` if the deadline passes first.
+    /// The part of the frame already read is kept, so reading again resumes the same frame.
+    /// Otherwise this method fails like `read_next_message`.
+    pub fn read_next_message_deadline(&mut self, deadline: Instant) -> Result<Vec<u8>> where T: ReadTimeout {
+        let mut reader = DeadlineReader::new(&mut self.reader, deadline)?;
+        read_message(&self.configuration, &mut self.sequence, &mut self.peer_closed, &mut self.partial, &mut reader)
+    }
+
+    /// Reads the next message from the MessageReader as a typed message
+    ///
+    /// The beginning boundary of the frame decides which variant of `M` is produced.
+    /// Typed messages are written without a digest or sequence number, so neither is checked.
+    ///
+    /// # Errors
+    /// This method will return `UnknownBoundary` if the frame's beginning boundary does not belong to `M`.
+    /// The unknown frame is still consumed, so the next read starts on the following frame.
+    pub fn read_next_typed_message<M: Message>(&mut self) -> Result<M> {
+        let boundaries = M::boundaries();
+        let (index, message) = self.read_next_tagged_message(boundaries)?;
+        M::from_frame(boundaries[index].0, message)
+    }
+}
+
+/// Reads the next message through `reader`, which is either the MessageReader's stream or a wrapper around it
+fn read_message(configuration: &StreamConfiguration, sequence: &mut SequenceTracker, peer_closed: &mut Option<Option<u64>>, partial: &mut PartialFrame, reader: &mut dyn Read) -> Result<Vec<u8>> {
+    if let Some(message) = sequence.take_held_back() {
+        return Ok(message);
+    }
+    if let Some(reason) = *peer_closed {
+        return Err(Error::from(ErrorKind::Closed { reason }));
+    }
+    let (error_beg, error_end) = configuration.control_boundaries("error");
+    let (close_beg, close_end) = configuration.control_boundaries("close");
+    let (ping_beg, ping_end) = configuration.control_boundaries("ping");
+    let (pong_beg, pong_end) = configuration.control_boundaries("pong");
+    let boundaries = [
+        (configuration.beginning_boundary.as_str(), configuration.ending_boundary.as_str()),
+        (error_beg.as_str(), error_end.as_str()),
+        (close_beg.as_str(), close_end.as_str()),
+        (ping_beg.as_str(), ping_end.as_str()),
+        (pong_beg.as_str(), pong_end.as_str()),
+    ];
+    loop {
+        match partial.read_tagged_message(reader, &configuration.delimiter_string, &boundaries)? {
+            (MESSAGE_FRAME, payload) => return decode_message(configuration, sequence, payload),
+            (ERROR_FRAME, payload) => return Err(decode_remote_error(&configuration.delimiter_string, payload)),
+            (CLOSE_FRAME, payload) => {
+                let reason = decode_close_reason(&configuration.delimiter_string, payload)?;
+                *peer_closed = Some(reason);
+                return Err(Error::from(ErrorKind::Closed { reason }));
+            }
+            // a heartbeat of the peer's, which only a DualMessenger can answer
+            _ => {}
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,273 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::result;
+use std::thread;
+use std::time::Duration;
+
+use super::{DualMessenger, Error, ErrorKind, Result, StreamConfiguration};
+
+/// How a ReconnectingMessenger waits between attempts to connect
+///
+/// The first retry waits `initial_delay`, and every retry after that waits twice as long as the one before, up to `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub(crate) initial_delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) max_attempts: Option<usize>,
+}
+
+impl Backoff {
+    /// Creates a new Backoff
+    ///
+    /// `max_delay` is raised to `initial_delay` if it is shorter. With `max_attempts` unset, connecting is retried forever;
+    /// otherwise it is at least 1.
+    pub fn new(initial_delay: Duration, max_delay: Duration, max_attempts: Option<usize>) -> Backoff {
+        Backoff {
+            initial_delay,
+            max_delay: max_delay.max(initial_delay),
+            max_attempts: max_attempts.map(|attempts| attempts.max(1)),
+        }
+    }
+
+    pub fn initial_delay(&self) -> Duration {
+        self.initial_delay
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    pub fn max_attempts(&self) -> Option<usize> {
+        self.max_attempts
+    }
+}
+
+impl Default for Backoff {
+    /// Retries forever, starting after 100 milliseconds and waiting at most 10 seconds between attempts
+    fn default() -> Backoff {
+        Backoff::new(Duration::from_millis(100), Duration::from_secs(10), None)
+    }
+}
+
+/// What a ReconnectingMessenger reports to the handler given to `with_events`
+#[derive(Debug)]
+pub enum ReconnectEvent<'a> {
+    /// The connection was lost with the given error
+    Disconnected(&'a Error),
+    /// An attempt to connect failed; `attempt` counts from 1 for every round of reconnecting
+    ConnectFailed { attempt: usize, error: &'a Error },
+    /// A connection was made, including the handshake, after the given number of attempts
+    Connected { attempts: usize },
+}
+
+/// A message that could not be sent, handed back along with the reason
+///
+/// The peer may or may not have received it, so resending it is left to the caller.
+#[derive(Debug)]
+pub struct FailedSend {
+    message: Vec<u8>,
+    error: Error,
+}
+
+impl FailedSend {
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    pub fn into_message(self) -> Vec<u8> {
+        self.message
+    }
+}
+
+type Connect<T> = Box<dyn FnMut() -> io::Result<T>>;
+type Handshake<T> = Box<dyn FnMut(&mut DualMessenger<T>) -> Result<()>>;
+type EventHandler = Box<dyn FnMut(&ReconnectEvent)>;
+
+/// A client DualMessenger that connects again whenever its connection is lost
+///
+/// Connecting happens on first use, and again on the first use after the connection is lost,
+/// waiting between attempts as the Backoff says.
+pub struct ReconnectingMessenger<T> where T: Read + Write {
+    configuration: StreamConfiguration,
+    connect: Connect<T>,
+    backoff: Backoff,
+    handshake: Option<Handshake<T>>,
+    events: Option<EventHandler>,
+    messenger: Option<DualMessenger<T>>,
+}
+
+impl<T: Read + Write> fmt::Debug for ReconnectingMessenger<T> where T: fmt::Debug {
+    fn fmt(&self, fmter: &mut fmt::Formatter) -> fmt::Result {
+        fmter.debug_struct("ReconnectingMessenger")
+            .field("configuration", &self.configuration)
+            .field("backoff", &self.backoff)
+            .field("messenger", &self.messenger)
+            .finish()
+    }
+}
+
+impl<T: Read + Write> ReconnectingMessenger<T> {
+
+    /// Initializes a new ReconnectingMessenger that opens its streams with `connect`
+    ///
+    /// No connection is made until the messenger is first used.
+    pub fn new<C>(config: StreamConfiguration, backoff: Backoff, connect: C) -> ReconnectingMessenger<T> where C: FnMut() -> io::Result<T> + 'static {
+        ReconnectingMessenger {
+            configuration: config,
+            connect: Box::new(connect),
+            backoff,
+            handshake: None,
+            events: None,
+            messenger: None,
+        }
+    }
+
+    /// Runs `handshake` on every new connection before it is used
+    ///
+    /// A handshake that fails counts as a failed attempt to connect.
+    pub fn with_handshake<H>(mut self, handshake: H) -> ReconnectingMessenger<T> where H: FnMut(&mut DualMessenger<T>) -> Result<()> + 'static {
+        self.handshake = Some(Box::new(handshake));
+        self
+    }
+
+    /// Reports every disconnection, failed attempt and new connection to `handler`
+    pub fn with_events<E>(mut self, handler: E) -> ReconnectingMessenger<T> where E: FnMut(&ReconnectEvent) + 'static {
+        self.events = Some(Box::new(handler));
+        self
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.messenger.is_some()
+    }
+
+    /// Returns the current connection, if there is one
+    pub fn messenger(&mut self) -> Option<&mut DualMessenger<T>> {
+        self.messenger.as_mut()
+    }
+
+    /// Hands over the current connection, connecting first if there is none
+    ///
+    /// This ReconnectingMessenger is left disconnected, so the next use connects again with the Backoff and handshake.
+    /// That is how a SessionMessenger is kept connected: start it on one connection taken from here,
+    /// and `resume` it on the next one whenever its connection is lost.
+    ///
+    /// # Errors
+    /// This method will return `Disconnected` if no connection could be made within the Backoff's attempts.
+    pub fn take_messenger(&mut self) -> Result<DualMessenger<T>> {
+        self.connected()?;
+        Ok(self.messenger.take().expect("connected() leaves a connection in place"))
+    }
+
+    /// Drops the current connection; the next use connects again
+    pub fn disconnect(&mut self) {
+        self.messenger = None;
+    }
+
+    /// Writes a message and flushes it, connecting first if needed
+    ///
+    /// # Errors
+    /// This method returns the message along with the error if it could not be sent. When the connection was lost
+    /// while sending, it is dropped and the next use connects again. The error is `Disconnected` if no connection
+    /// could be made within the Backoff's attempts, and `Closed` if the current connection was closed on this side;
+    /// that does not count as losing it, so call `disconnect` to connect again.
+    pub fn send(&mut self, message: Vec<u8>) -> result::Result<usize, FailedSend> {
+        if let Err(error) = self.connected() {
+            return Err(FailedSend { message, error });
+        }
+        let result = {
+            let messenger = self.messenger.as_mut().expect("connected() leaves a connection in place");
+            messenger.write(&message).and_then(|written| messenger.flush().map(|_| written))
+        };
+        result.map_err(|error| {
+            let error = unwrap_io_error(error);
+            if is_connection_lost(&error) {
+                self.lost(&error);
+            }
+            FailedSend { message, error }
+        })
+    }
+
+    /// Reads the next message, connecting again and carrying on whenever the connection is lost
+    ///
+    /// # Errors
+    /// This method will return `Disconnected` if no connection could be made within the Backoff's attempts.
+    /// Read timeouts, `Closed` and every error that does not mean the connection was lost are returned as they are.
+    pub fn read_next_message(&mut self) -> Result<Vec<u8>> {
+        loop {
+            self.connected()?;
+            let result = self.messenger.as_mut().expect("connected() leaves a connection in place").read_next_message();
+            match result {
+                Err(ref error) if is_connection_lost(error) => self.lost(error),
+                result => return result,
+            }
+        }
+    }
+
+    fn connected(&mut self) -> Result<()> {
+        if self.messenger.is_some() {
+            return Ok(());
+        }
+        let mut delay = self.backoff.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match self.try_connect() {
+                Ok(messenger) => {
+                    self.messenger = Some(messenger);
+                    self.report(&ReconnectEvent::Connected { attempts: attempt });
+                    return Ok(());
+                }
+                Err(error) => self.report(&ReconnectEvent::ConnectFailed { attempt, error: &error }),
+            }
+            if self.backoff.max_attempts.is_some_and(|max_attempts| attempt >= max_attempts) {
+                return Err(Error::from(ErrorKind::Disconnected));
+            }
+            thread::sleep(delay);
+            delay = delay.checked_mul(2).map_or(self.backoff.max_delay, |doubled| doubled.min(self.backoff.max_delay));
+            attempt += 1;
+        }
+    }
+
+    fn try_connect(&mut self) -> Result<DualMessenger<T>> {
+        let stream = (self.connect)()?;
+        let mut messenger = DualMessenger::new_from_config(self.configuration.clone(), stream);
+        if let Some(ref mut handshake) = self.handshake {
+            handshake(&mut messenger)?;
+        }
+        Ok(messenger)
+    }
+
+    fn lost(&mut self, error: &Error) {
+        self.messenger = None;
+        self.report(&ReconnectEvent::Disconnected(error));
+    }
+
+    fn report(&mut self, event: &ReconnectEvent) {
+        if let Some(ref mut events) = self.events {
+            events(event);
+        }
+    }
+}
+
+/// Recovers the messenger's own error from an io::Error that carries one, such as `Closed` after a local close
+fn unwrap_io_error(error: io::Error) -> Error {
+    if !error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+        return Error::from(error);
+    }
+    match error.into_inner().map(|inner| inner.downcast::<Error>()) {
+        Some(Ok(inner)) => *inner,
+        _ => unreachable!("the io::Error was checked to carry an Error"),
+    }
+}
+
+/// Returns whether an error means the stream is gone, rather than that one read or message went wrong
+fn is_connection_lost(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::BufferEmpty => true,
+        ErrorKind::IOError(_) => !error.is_read_timeout(),
+        _ => false,
+    }
+}
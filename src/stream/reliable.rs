@@ -0,0 +1,193 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use super::frame_header::{prepend_digest, split_digest, split_header_fields};
+use super::replay::{write_next_expected, ReplayBuffer, ACK_FRAME, DATA_FRAME};
+use super::{DualMessenger, ErrorKind, Result};
+
+// index of the frame kind `ReplayBuffer::read_frame` is given on top of its own
+const NACK_FRAME: usize = 2;
+
+/// Settings for a ReliableMessenger
+///
+/// At most `send_window` messages are sent without being acknowledged; sending more waits for acknowledgements.
+/// Messages still unacknowledged after `retransmit_timeout` are sent again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reliability {
+    pub(crate) send_window: usize,
+    pub(crate) retransmit_timeout: Duration,
+}
+
+impl Reliability {
+    /// Creates a new Reliability
+    ///
+    /// `send_window` is raised to 1 if it is smaller, so a message can always be sent.
+    pub fn new(send_window: usize, retransmit_timeout: Duration) -> Reliability {
+        Reliability {
+            send_window: send_window.max(1),
+            retransmit_timeout,
+        }
+    }
+
+    pub fn send_window(&self) -> usize {
+        self.send_window
+    }
+
+    pub fn retransmit_timeout(&self) -> Duration {
+        self.retransmit_timeout
+    }
+}
+
+impl Default for Reliability {
+    /// A window of 32 messages, retransmitted after a second
+    fn default() -> Reliability {
+        Reliability::new(32, Duration::from_secs(1))
+    }
+}
+
+/// Delivers messages exactly once and in order over a link that can drop or corrupt frames
+///
+/// Every message is sent in a data frame with a sequence number, and the receiving side answers with cumulative
+/// acknowledgements. Unacknowledged messages are sent again once they time out, and when the receiving side reports
+/// a corrupted frame; frames that arrive out of order are dropped and sent again with the rest.
+/// Corruption is only noticed when the configuration has hashing enabled.
+///
+/// Retransmission happens while the ReliableMessenger reads from the stream, so a blocking stream should have a read
+/// timeout (such as `TcpStream::set_read_timeout`) to retransmit while the peer is silent.
+/// Both ends of the stream must use a ReliableMessenger.
+pub struct ReliableMessenger<T> where T: Read + Write {
+    messenger: DualMessenger<T>,
+    reliability: Reliability,
+    nack_boundaries: (String, String),
+    replay: ReplayBuffer,
+}
+
+impl<T: Read + Write> ReliableMessenger<T> {
+
+    /// Initializes a new ReliableMessenger over a DualMessenger
+    ///
+    /// Data frames are written between the DualMessenger's boundaries; acknowledgements use reserved control boundaries.
+    pub fn new(messenger: DualMessenger<T>, reliability: Reliability) -> ReliableMessenger<T> {
+        let nack_boundaries = messenger.configuration().control_boundaries("nack");
+        let replay = ReplayBuffer::new(messenger.configuration());
+        ReliableMessenger {
+            messenger,
+            reliability,
+            nack_boundaries,
+            replay,
+        }
+    }
+
+    /// Returns how many sent messages have not been acknowledged yet
+    pub fn unacknowledged(&self) -> usize {
+        self.replay.unacknowledged()
+    }
+
+    /// Sends a message, waiting for acknowledgements first if the send window is full
+    ///
+    /// Messages that arrive while waiting are kept for `receive`.
+    ///
+    /// # Errors
+    /// This method will return Err if the stream fails while waiting or writing.
+    pub fn send(&mut self, message: &[u8]) -> Result<()> {
+        while self.replay.unacknowledged() >= self.reliability.send_window {
+            match self.process_next_frame() {
+                Ok(()) => continue,
+                Err(ref e) if e.is_read_timeout() => self.retransmit_expired()?,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let configuration = self.messenger.configuration();
+        let mut payload = self.replay.number(&configuration.delimiter_string, message);
+        if configuration.hashing_enabled {
+            payload = prepend_digest(&configuration.delimiter_string, &payload);
+        }
+        self.replay.write_data(&mut self.messenger, &payload)?;
+        self.replay.keep(payload);
+        Ok(())
+    }
+
+    /// Receives the next message in the order it was sent
+    ///
+    /// Acknowledgements that arrive in the meantime are processed, and timed out messages are sent again.
+    ///
+    /// # Errors
+    /// This method will return `BufferEmpty` if the stream ends before a message arrives.
+    /// This method will return the stream's `WouldBlock` or `TimedOut` error if no frame arrives within its read timeout.
+    pub fn receive(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(message) = self.replay.take_delivered() {
+                return Ok(message);
+            }
+            self.retransmit_expired()?;
+            self.process_next_frame()?;
+        }
+    }
+
+    /// Processes whatever frames can be read before the stream's read timeout, then sends timed out messages again
+    ///
+    /// Messages that arrive are kept for `receive`.
+    pub fn poll(&mut self) -> Result<()> {
+        loop {
+            match self.process_next_frame() {
+                Ok(()) => continue,
+                Err(ref e) if e.is_read_timeout() => return self.retransmit_expired(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn release(self) -> DualMessenger<T> {
+        self.messenger
+    }
+
+    fn process_next_frame(&mut self) -> Result<()> {
+        let nack_boundaries = [(self.nack_boundaries.0.as_str(), self.nack_boundaries.1.as_str())];
+        let (index, payload) = match self.replay.read_frame(&mut self.messenger, &nack_boundaries)? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let delimiter_string = self.messenger.configuration().delimiter_string.clone();
+        match index {
+            DATA_FRAME => self.receive_data(&delimiter_string, payload),
+            ACK_FRAME => {
+                let (fields, _) = split_header_fields(&delimiter_string, 1, payload)?;
+                self.replay.acknowledge(fields[0]);
+                Ok(())
+            }
+            NACK_FRAME => {
+                let (fields, _) = split_header_fields(&delimiter_string, 1, payload)?;
+                self.replay.acknowledge(fields[0]);
+                self.replay.resend_all(&mut self.messenger)
+            }
+            _ => unreachable!("only three kinds of frame are read"),
+        }
+    }
+
+    fn receive_data(&mut self, delimiter_string: &str, mut payload: Vec<u8>) -> Result<()> {
+        if self.messenger.configuration().hashing_enabled {
+            payload = match split_digest(delimiter_string, payload) {
+                Ok(v) => v,
+                // the sender resends everything from the first message not yet received
+                Err(ref e) if *e.kind() == ErrorKind::HashMismatch => {
+                    return write_next_expected(&mut self.messenger, &self.nack_boundaries, self.replay.next_expected());
+                }
+                Err(e) => return Err(e),
+            };
+        }
+        let (fields, message) = split_header_fields(delimiter_string, 1, payload)?;
+        // duplicates and frames after a lost one are dropped; the acknowledgement tells the sender what is missing
+        self.replay.accept(fields[0], message);
+        self.replay.write_ack(&mut self.messenger)
+    }
+
+    /// Sends every unacknowledged message again once the oldest has timed out
+    fn retransmit_expired(&mut self) -> Result<()> {
+        if self.replay.oldest_expired(self.reliability.retransmit_timeout) {
+            return self.replay.resend_all(&mut self.messenger);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use super::frame_header::prepend_header_fields;
+use super::{DualMessenger, ErrorKind, Result, StreamConfiguration};
+
+// indices of the frame kinds `ReplayBuffer::read_frame` returns; other boundaries it is given follow these
+pub(crate) const DATA_FRAME: usize = 0;
+pub(crate) const ACK_FRAME: usize = 1;
+
+struct SentFrame {
+    sequence: u64,
+    payload: Vec<u8>,
+    sent_at: Instant,
+}
+
+/// The acknowledgement and replay bookkeeping of ReliableMessenger and SessionMessenger
+///
+/// Data frames are written between the configured boundaries with a sequence number ahead of the message,
+/// and kept until a cumulative acknowledgement says the peer has them. Messages received are delivered once each, in order.
+pub(crate) struct ReplayBuffer {
+    data_boundaries: (String, String),
+    ack_boundaries: (String, String),
+    next_sequence: u64,
+    unacknowledged: VecDeque<SentFrame>,
+    next_expected: u64,
+    delivered: VecDeque<Vec<u8>>,
+}
+
+impl ReplayBuffer {
+
+    pub(crate) fn new(configuration: &StreamConfiguration) -> ReplayBuffer {
+        ReplayBuffer {
+            data_boundaries: (configuration.beginning_boundary.clone(), configuration.ending_boundary.clone()),
+            ack_boundaries: configuration.control_boundaries("ack"),
+            next_sequence: 0,
+            unacknowledged: VecDeque::new(),
+            next_expected: 0,
+            delivered: VecDeque::new(),
+        }
+    }
+
+    /// Returns how many sent messages have not been acknowledged yet
+    pub(crate) fn unacknowledged(&self) -> usize {
+        self.unacknowledged.len()
+    }
+
+    /// Returns the sequence number of the first message not received yet
+    pub(crate) fn next_expected(&self) -> u64 {
+        self.next_expected
+    }
+
+    /// Returns the sequence number of the oldest message kept, or of the next one to be sent if none is
+    pub(crate) fn oldest_sequence(&self) -> u64 {
+        self.unacknowledged.front().map_or(self.next_sequence, |frame| frame.sequence)
+    }
+
+    /// Returns whether the oldest message kept was last sent at least `timeout` ago
+    pub(crate) fn oldest_expired(&self, timeout: Duration) -> bool {
+        self.unacknowledged.front().is_some_and(|frame| frame.sent_at.elapsed() >= timeout)
+    }
+
+    pub(crate) fn take_delivered(&mut self) -> Option<Vec<u8>> {
+        self.delivered.pop_front()
+    }
+
+    /// Puts the next sequence number ahead of a message, as the payload of its data frame
+    pub(crate) fn number(&self, delimiter_string: &str, message: &[u8]) -> Vec<u8> {
+        prepend_header_fields(delimiter_string, &[self.next_sequence], message)
+    }
+
+    /// Keeps the payload made by `number` until it is acknowledged, moving on to the next sequence number
+    pub(crate) fn keep(&mut self, payload: Vec<u8>) {
+        self.unacknowledged.push_back(SentFrame {
+            sequence: self.next_sequence,
+            payload,
+            sent_at: Instant::now(),
+        });
+        self.next_sequence += 1;
+    }
+
+    /// Forgets every sent message before `next_expected`, which the peer has received
+    pub(crate) fn acknowledge(&mut self, next_expected: u64) {
+        while self.unacknowledged.front().is_some_and(|frame| frame.sequence < next_expected) {
+            self.unacknowledged.pop_front();
+        }
+    }
+
+    /// Delivers a received message if it is the next one; duplicates and messages after a missing one are dropped
+    pub(crate) fn accept(&mut self, sequence: u64, message: Vec<u8>) {
+        if sequence == self.next_expected {
+            self.next_expected += 1;
+            self.delivered.push_back(message);
+        }
+    }
+
+    /// Reads the next data frame, acknowledgement, or frame between one of `other_boundaries`
+    ///
+    /// Returns `None` when the frame was other traffic on the stream, which is skipped.
+    pub(crate) fn read_frame<T: Read + Write>(&self, messenger: &mut DualMessenger<T>, other_boundaries: &[(&str, &str)]) -> Result<Option<(usize, Vec<u8>)>> {
+        let mut boundaries = vec![
+            (self.data_boundaries.0.as_str(), self.data_boundaries.1.as_str()),
+            (self.ack_boundaries.0.as_str(), self.ack_boundaries.1.as_str()),
+        ];
+        boundaries.extend_from_slice(other_boundaries);
+        match messenger.read_next_tagged_message(&boundaries) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(ref e) if matches!(*e.kind(), ErrorKind::UnknownBoundary(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes a data frame and flushes it
+    pub(crate) fn write_data<T: Read + Write>(&self, messenger: &mut DualMessenger<T>, payload: &[u8]) -> Result<()> {
+        messenger.write_between(&self.data_boundaries.0, &self.data_boundaries.1, payload)?;
+        messenger.flush()?;
+        Ok(())
+    }
+
+    /// Writes the data frame of the message kept last and flushes it
+    pub(crate) fn write_newest<T: Read + Write>(&self, messenger: &mut DualMessenger<T>) -> Result<()> {
+        match self.unacknowledged.back() {
+            Some(frame) => self.write_data(messenger, &frame.payload),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends every unacknowledged message again
+    pub(crate) fn resend_all<T: Read + Write>(&mut self, messenger: &mut DualMessenger<T>) -> Result<()> {
+        for frame in &mut self.unacknowledged {
+            messenger.write_between(&self.data_boundaries.0, &self.data_boundaries.1, &frame.payload)?;
+            frame.sent_at = Instant::now();
+        }
+        messenger.flush()?;
+        Ok(())
+    }
+
+    /// Tells the peer which message this side expects next
+    pub(crate) fn write_ack<T: Read + Write>(&self, messenger: &mut DualMessenger<T>) -> Result<()> {
+        write_next_expected(messenger, &self.ack_boundaries, self.next_expected)
+    }
+}
+
+/// Writes a control frame carrying the sequence number of the first message not received, and flushes it
+pub(crate) fn write_next_expected<T: Read + Write>(messenger: &mut DualMessenger<T>, boundaries: &(String, String), next_expected: u64) -> Result<()> {
+    let payload = prepend_header_fields(&messenger.configuration().delimiter_string, &[next_expected], &[]);
+    messenger.write_between(&boundaries.0, &boundaries.1, &payload)?;
+    messenger.flush()?;
+    Ok(())
+}
@@ -0,0 +1,662 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::result;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::frame_header::{prepend_header_fields, split_header_fields};
+use super::{DualMessenger, MessageReader, MessageWriter, Error, ErrorKind, ReadTimeout, Result};
+
+// statuses of the frames a server sends back
+const RESPONSE_OK: u64 = 0;
+const RESPONSE_UNKNOWN_METHOD: u64 = 1;
+const RESPONSE_STREAM_ITEM: u64 = 2;
+const RESPONSE_STREAM_END: u64 = 3;
+const RESPONSE_STREAM_ERROR: u64 = 4;
+
+// kinds of the stream frames a client sends after its request
+const STREAM_ITEM: u64 = 0;
+const STREAM_END: u64 = 1;
+const STREAM_CANCEL: u64 = 2;
+
+/// How long a streaming handler's `send` waits for frames from the client before going on without them
+const PENDING_FRAMES_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Encodes a request as its correlation id and method name ahead of the request body
+fn encode_request(delimiter_string: &str, id: u64, method: &str, request: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(method.len() + request.len());
+    body.extend_from_slice(method.as_bytes());
+    body.extend_from_slice(request);
+    prepend_header_fields(delimiter_string, &[id, method.len() as u64], &body)
+}
+
+fn decode_request(delimiter_string: &str, payload: Vec<u8>) -> Result<(u64, String, Vec<u8>)> {
+    let (fields, mut body) = split_header_fields(delimiter_string, 2, payload)?;
+    let method_size = fields[1] as usize;
+    if method_size > body.len() {
+        return Err(Error::from(ErrorKind::BufferDoesntContainDelimiter));
+    }
+    let request = body.split_off(method_size);
+    Ok((fields[0], String::from_utf8(body)?, request))
+}
+
+/// Turns a status the server ended a call with into the error it stands for
+fn status_error(status: u64, method: &str, response: &[u8]) -> Option<Error> {
+    match status {
+        RESPONSE_UNKNOWN_METHOD => Some(Error::from(ErrorKind::UnknownMethod(String::from(method)))),
+        RESPONSE_STREAM_ERROR => Some(Error::from(ErrorKind::StreamFailed(String::from_utf8_lossy(response).into_owned()))),
+        _ => None,
+    }
+}
+
+struct ClientState {
+    // every unary call still waiting, along with its response once it arrives
+    calls: HashMap<u64, Option<(u64, Vec<u8>)>>,
+    // every streaming call still open, along with the frames that arrived for it
+    streams: HashMap<u64, VecDeque<(u64, Vec<u8>)>>,
+    disconnected: bool,
+}
+
+struct ClientShared {
+    state: Mutex<ClientState>,
+    arrived: Condvar,
+}
+
+impl ClientShared {
+    fn lock(&self) -> MutexGuard<'_, ClientState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits for the state to change, giving up at the deadline
+    fn wait_until<'s>(&self, state: MutexGuard<'s, ClientState>, deadline: Instant) -> Result<MutexGuard<'s, ClientState>> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::from(ErrorKind::TimedOut));
+        }
+        Ok(self.arrived.wait_timeout(state, deadline - now).unwrap_or_else(|poisoned| poisoned.into_inner()).0)
+    }
+}
+
+/// Reads responses until the stream ends or fails, handing each to the call waiting on its correlation id
+fn read_responses<R: Read>(mut reader: MessageReader<R>, shared: Arc<ClientShared>) {
+    let delimiter_string = reader.configuration().delimiter_string.clone();
+    let (response_beg, response_end) = reader.configuration().control_boundaries("response");
+    loop {
+        let payload = match reader.read_next_tagged_message(&[(&response_beg, &response_end)]) {
+            Ok((_, payload)) => payload,
+            // only a stream that ended or failed stops the thread; other traffic and frames that do not read are skipped
+            Err(e) => match *e.kind() {
+                ErrorKind::BufferEmpty => break,
+                ErrorKind::IOError(_) if !e.is_read_timeout() => break,
+                _ => continue,
+            },
+        };
+        let (fields, response) = match split_header_fields(&delimiter_string, 2, payload) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let (id, status) = (fields[0], fields[1]);
+        let mut state = shared.lock();
+        // responses to calls that already timed out or were cancelled are dropped
+        if let Some(slot) = state.calls.get_mut(&id) {
+            // a unary call waits for the first frame, so only the first item of a stream answers it
+            if slot.is_none() {
+                *slot = Some((status, response));
+            }
+        } else if let Some(queue) = state.streams.get_mut(&id) {
+            // a unary handler answering a streaming call produces a stream of one item
+            if status == RESPONSE_OK {
+                queue.push_back((RESPONSE_STREAM_ITEM, response));
+                queue.push_back((RESPONSE_STREAM_END, Vec::new()));
+            } else {
+                queue.push_back((status, response));
+            }
+        }
+        shared.arrived.notify_all();
+    }
+    // every call still waiting fails with `Disconnected` once it sees this
+    shared.lock().disconnected = true;
+    shared.arrived.notify_all();
+}
+
+/// The calling side of a request/response conversation
+///
+/// Every request carries a correlation id that its response echoes back, so several calls can be in flight at once,
+/// from one thread through `start_call` or from many threads sharing the client.
+/// Responses are read on a background thread, which skips any frame it cannot read and stops once the stream ends or fails.
+/// Every call still waiting then fails with `Disconnected`.
+pub struct RpcClient<W> where W: Write {
+    writer: Mutex<MessageWriter<W>>,
+    shared: Arc<ClientShared>,
+    next_id: AtomicU64,
+    timeout: Duration,
+}
+
+impl<W: Write> RpcClient<W> {
+
+    /// Initializes a new RpcClient over the two halves of a stream
+    ///
+    /// A socket can provide both halves through `try_clone`.
+    /// Calls time out after 30 seconds unless `set_timeout` says otherwise.
+    pub fn new<R>(reader: MessageReader<R>, writer: MessageWriter<W>) -> RpcClient<W> where R: Read + Send + 'static {
+        let shared = Arc::new(ClientShared {
+            state: Mutex::new(ClientState {
+                calls: HashMap::new(),
+                streams: HashMap::new(),
+                disconnected: false,
+            }),
+            arrived: Condvar::new(),
+        });
+        let reader_shared = shared.clone();
+        thread::spawn(move || read_responses(reader, reader_shared));
+
+        RpcClient {
+            writer: Mutex::new(writer),
+            shared,
+            next_id: AtomicU64::new(0),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets how long a call, or each item of a streaming call, is waited for
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Calls a method on the server and waits for its response
+    ///
+    /// Calling a streaming method this way returns its first item.
+    ///
+    /// # Errors
+    /// This method will return `TimedOut` if no response arrives within the client's timeout.
+    /// This method will return `UnknownMethod` if the server has no handler for `method`.
+    /// This method will return `Disconnected` if the stream ends before the response arrives.
+    pub fn call(&self, method: &str, request: &[u8]) -> Result<Vec<u8>> {
+        self.start_call(method, request)?.wait()
+    }
+
+    /// Sends a request without waiting for its response
+    pub fn start_call(&self, method: &str, request: &[u8]) -> Result<PendingCall<'_, W>> {
+        let id = self.register_call(|state, id| { state.calls.insert(id, None); })?;
+        let pending = PendingCall {
+            client: self,
+            id,
+            method: String::from(method),
+        };
+        self.send_request(id, method, request)?;
+        Ok(pending)
+    }
+
+    /// Sends a request whose response is a stream of items
+    ///
+    /// The returned StreamingCall can also send a stream of items to the server, making the call bidirectional.
+    pub fn start_stream(&self, method: &str, request: &[u8]) -> Result<StreamingCall<'_, W>> {
+        let id = self.register_call(|state, id| { state.streams.insert(id, VecDeque::new()); })?;
+        let streaming = StreamingCall {
+            client: self,
+            id,
+            method: String::from(method),
+            finished_sending: false,
+            ended: false,
+        };
+        self.send_request(id, method, request)?;
+        Ok(streaming)
+    }
+
+    fn register_call<F>(&self, register: F) -> Result<u64> where F: FnOnce(&mut ClientState, u64) {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut state = self.shared.lock();
+        if state.disconnected {
+            return Err(Error::from(ErrorKind::Disconnected));
+        }
+        register(&mut state, id);
+        Ok(id)
+    }
+
+    fn send_request(&self, id: u64, method: &str, request: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (request_beg, request_end) = writer.configuration().control_boundaries("request");
+        let payload = encode_request(&writer.configuration().delimiter_string, id, method, request);
+        writer.write_between(&request_beg, &request_end, &payload)?;
+        writer.flush()
+    }
+
+    fn send_stream_frame(&self, id: u64, kind: u64, item: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (stream_beg, stream_end) = writer.configuration().control_boundaries("stream");
+        let payload = prepend_header_fields(&writer.configuration().delimiter_string, &[id, kind], item);
+        writer.write_between(&stream_beg, &stream_end, &payload)?;
+        writer.flush()
+    }
+}
+
+/// A call whose request has been sent but whose response has not been taken yet
+///
+/// Dropping a PendingCall abandons it; its response is discarded when it arrives.
+pub struct PendingCall<'a, W> where W: Write + 'a {
+    client: &'a RpcClient<W>,
+    id: u64,
+    method: String,
+}
+
+impl<'a, W: Write> PendingCall<'a, W> {
+
+    /// Returns the correlation id carried by the request and its response
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Waits for the response within the client's timeout
+    ///
+    /// See `RpcClient::call`.
+    pub fn wait(self) -> Result<Vec<u8>> {
+        let timeout = self.client.timeout;
+        self.wait_timeout(timeout)
+    }
+
+    pub fn wait_timeout(self, timeout: Duration) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        let shared = &self.client.shared;
+        let mut state = shared.lock();
+        loop {
+            if let Some(&Some(_)) = state.calls.get(&self.id) {
+                let (status, response) = state.calls.remove(&self.id).and_then(|slot| slot).expect("the response was just found");
+                return match status_error(status, &self.method, &response) {
+                    Some(e) => Err(e),
+                    None if status == RESPONSE_STREAM_END => Ok(Vec::new()),
+                    None => Ok(response),
+                };
+            }
+            if state.disconnected {
+                return Err(Error::from(ErrorKind::Disconnected));
+            }
+            state = shared.wait_until(state, deadline)?;
+        }
+    }
+}
+
+impl<'a, W: Write> Drop for PendingCall<'a, W> {
+    fn drop(&mut self) {
+        self.client.shared.lock().calls.remove(&self.id);
+    }
+}
+
+/// A call whose response is a stream of items sharing its correlation id
+///
+/// Iterating yields each item until the server ends the stream; an error ends the iteration after it is yielded.
+/// Dropping a StreamingCall before its stream ends cancels it.
+pub struct StreamingCall<'a, W> where W: Write + 'a {
+    client: &'a RpcClient<W>,
+    id: u64,
+    method: String,
+    finished_sending: bool,
+    ended: bool,
+}
+
+impl<'a, W: Write> StreamingCall<'a, W> {
+
+    /// Returns the correlation id carried by the request and every frame of the stream
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Sends one more item to the server's handler
+    ///
+    /// # Errors
+    /// This method will return `Cancelled` once the call has ended or `finish_sending` has been called.
+    pub fn send(&mut self, item: &[u8]) -> Result<()> {
+        if self.finished_sending || self.ended {
+            return Err(Error::from(ErrorKind::Cancelled));
+        }
+        Ok(self.client.send_stream_frame(self.id, STREAM_ITEM, item)?)
+    }
+
+    /// Tells the server's handler that no more items will be sent
+    pub fn finish_sending(&mut self) -> Result<()> {
+        if self.finished_sending || self.ended {
+            return Ok(());
+        }
+        self.finished_sending = true;
+        Ok(self.client.send_stream_frame(self.id, STREAM_END, &[])?)
+    }
+
+    /// Waits for the next item of the stream
+    ///
+    /// Returns `None` once the server has ended the stream.
+    ///
+    /// # Errors
+    /// This method will return `TimedOut` if no item arrives in time; the stream stays open.
+    /// This method will return `StreamFailed` if the server's handler ended the stream with an error.
+    /// This method will return `UnknownMethod` if the server has no handler for the method.
+    /// This method will return `Disconnected` if the stream ends before the server ends the call.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + timeout;
+        let shared = &self.client.shared;
+        let mut state = shared.lock();
+        loop {
+            if self.ended {
+                return Ok(None);
+            }
+            if let Some((status, response)) = state.streams.get_mut(&self.id).and_then(|queue| queue.pop_front()) {
+                if status == RESPONSE_STREAM_ITEM {
+                    return Ok(Some(response));
+                }
+                self.ended = true;
+                state.streams.remove(&self.id);
+                return match status_error(status, &self.method, &response) {
+                    Some(e) => Err(e),
+                    None => Ok(None),
+                };
+            }
+            if state.disconnected {
+                self.ended = true;
+                return Err(Error::from(ErrorKind::Disconnected));
+            }
+            state = shared.wait_until(state, deadline)?;
+        }
+    }
+
+    /// Stops the stream, telling the server and discarding any items still on their way
+    pub fn cancel(mut self) -> Result<()> {
+        self.cancel_stream()
+    }
+
+    fn cancel_stream(&mut self) -> Result<()> {
+        let was_ended = self.ended;
+        self.ended = true;
+        self.client.shared.lock().streams.remove(&self.id);
+        if !was_ended {
+            self.client.send_stream_frame(self.id, STREAM_CANCEL, &[])?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Iterator for StreamingCall<'a, W> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>>> {
+        let timeout = self.client.timeout;
+        match self.next_timeout(timeout) {
+            Ok(item) => item.map(Ok),
+            Err(e) => {
+                let _ = self.cancel_stream();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a, W: Write> Drop for StreamingCall<'a, W> {
+    fn drop(&mut self) {
+        let _ = self.cancel_stream();
+    }
+}
+
+/// Requests and stream frames a server has read but not handled yet
+struct ServerInbox {
+    requests: VecDeque<(u64, String, Vec<u8>)>,
+    stream_frames: HashMap<u64, VecDeque<(u64, Vec<u8>)>>,
+    active_stream: Option<u64>,
+}
+
+impl ServerInbox {
+
+    /// Reads one frame from the stream and files it for later
+    fn receive<T: Read + Write>(&mut self, messenger: &mut DualMessenger<T>) -> Result<()> {
+        let (request_beg, request_end) = messenger.configuration().control_boundaries("request");
+        let (stream_beg, stream_end) = messenger.configuration().control_boundaries("stream");
+        let frame = messenger.read_next_tagged_message(&[(&request_beg, &request_end), (&stream_beg, &stream_end)])?;
+        self.file(&messenger.configuration().delimiter_string, frame)
+    }
+
+    /// Files every frame that has already arrived, without waiting for more than a moment
+    ///
+    /// Frames that are not requests or stream frames, or that do not decode, are skipped.
+    fn receive_pending<T: Read + Write + ReadTimeout>(&mut self, messenger: &mut DualMessenger<T>) -> Result<()> {
+        let (request_beg, request_end) = messenger.configuration().control_boundaries("request");
+        let (stream_beg, stream_end) = messenger.configuration().control_boundaries("stream");
+        loop {
+            let boundaries = [(request_beg.as_str(), request_end.as_str()), (stream_beg.as_str(), stream_end.as_str())];
+            let result = messenger.read_next_tagged_message_deadline(&boundaries, Instant::now() + PENDING_FRAMES_TIMEOUT)
+                .and_then(|frame| self.file(&messenger.configuration().delimiter_string, frame));
+            match result {
+                Ok(()) => continue,
+                Err(e) => match *e.kind() {
+                    ErrorKind::BufferEmpty => return Ok(()),
+                    ErrorKind::IOError(_) if e.is_read_timeout() => return Ok(()),
+                    ErrorKind::IOError(_) => return Err(e),
+                    _ => continue,
+                },
+            }
+        }
+    }
+
+    /// Files a frame read from the stream under the request or call it belongs to
+    fn file(&mut self, delimiter_string: &str, (index, payload): (usize, Vec<u8>)) -> Result<()> {
+        if index == 0 {
+            self.requests.push_back(decode_request(delimiter_string, payload)?);
+            return Ok(());
+        }
+
+        let (fields, item) = split_header_fields(delimiter_string, 2, payload)?;
+        let (id, kind) = (fields[0], fields[1]);
+        match self.requests.iter().position(|&(request_id, _, _)| request_id == id) {
+            // a call cancelled before it was handled is never handled at all
+            Some(waiting) if kind == STREAM_CANCEL => {
+                self.requests.remove(waiting);
+                self.stream_frames.remove(&id);
+            }
+            Some(_) => self.stream_frames.entry(id).or_default().push_back((kind, item)),
+            None if self.active_stream == Some(id) => self.stream_frames.entry(id).or_default().push_back((kind, item)),
+            // frames for calls that already finished are dropped
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// The server's side of a streaming call, handed to streaming handlers
+///
+/// A handler sends as many items as it likes, and can receive the items the client sends for bidirectional calls.
+/// The server ends the stream once the handler returns.
+/// Every `send` and `is_cancelled` first takes in whatever the client has already sent, so a cancellation is noticed
+/// even by a handler that never calls `recv`.
+pub struct ResponseStream<'s, T> where T: Read + Write + 's {
+    id: u64,
+    messenger: &'s mut DualMessenger<T>,
+    inbox: &'s mut ServerInbox,
+    finished_receiving: bool,
+    cancelled: bool,
+}
+
+impl<'s, T: Read + Write + ReadTimeout> ResponseStream<'s, T> {
+
+    /// Returns whether the client has cancelled the call, taking in the frames it has sent so far
+    pub fn is_cancelled(&mut self) -> bool {
+        // a stream that failed has nobody left to cancel, and the next send reports the failure
+        let _ = self.inbox.receive_pending(self.messenger);
+        self.seen_cancel()
+    }
+
+    /// Sends one item of the response stream
+    ///
+    /// The frames the client has sent so far are taken in first.
+    ///
+    /// # Errors
+    /// This method will return `Cancelled` once the client has cancelled the call.
+    pub fn send(&mut self, item: &[u8]) -> Result<()> {
+        self.inbox.receive_pending(self.messenger)?;
+        if self.seen_cancel() {
+            return Err(Error::from(ErrorKind::Cancelled));
+        }
+        Ok(write_response(self.messenger, self.id, RESPONSE_STREAM_ITEM, item)?)
+    }
+}
+
+impl<'s, T: Read + Write> ResponseStream<'s, T> {
+
+    /// Returns whether a cancellation from the client has already been read off the stream
+    fn seen_cancel(&mut self) -> bool {
+        if let Some(frames) = self.inbox.stream_frames.get(&self.id) {
+            if frames.iter().any(|&(kind, _)| kind == STREAM_CANCEL) {
+                self.cancelled = true;
+            }
+        }
+        self.cancelled
+    }
+
+    /// Waits for the next item the client sends
+    ///
+    /// Returns `None` once the client has finished sending.
+    /// Requests for other calls that arrive in the meantime are handled after this call.
+    ///
+    /// # Errors
+    /// This method will return `Cancelled` if the client cancels the call.
+    pub fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            if self.cancelled {
+                return Err(Error::from(ErrorKind::Cancelled));
+            }
+            if self.finished_receiving {
+                return Ok(None);
+            }
+            match self.inbox.stream_frames.get_mut(&self.id).and_then(|frames| frames.pop_front()) {
+                Some((STREAM_ITEM, item)) => return Ok(Some(item)),
+                Some((STREAM_END, _)) => self.finished_receiving = true,
+                Some(_) => self.cancelled = true,
+                None => self.inbox.receive(self.messenger)?,
+            }
+        }
+    }
+}
+
+fn write_response<T: Read + Write>(messenger: &mut DualMessenger<T>, id: u64, status: u64, response: &[u8]) -> io::Result<()> {
+    let (response_beg, response_end) = messenger.configuration().control_boundaries("response");
+    let payload = prepend_header_fields(&messenger.configuration().delimiter_string, &[id, status], response);
+    messenger.write_between(&response_beg, &response_end, &payload)?;
+    messenger.flush()
+}
+
+type UnaryHandler<'a> = Box<dyn FnMut(Vec<u8>) -> Vec<u8> + 'a>;
+type StreamingHandler<'a, T> = Box<dyn FnMut(Vec<u8>, &mut ResponseStream<T>) -> result::Result<(), String> + 'a>;
+
+enum Handler<'a, T> where T: Read + Write {
+    Unary(UnaryHandler<'a>),
+    Streaming(StreamingHandler<'a, T>),
+}
+
+/// The answering side of a request/response conversation
+///
+/// Requests are handled one at a time, in the order they arrive, by the handler registered for their method.
+pub struct RpcServer<'a, T> where T: Read + Write {
+    messenger: DualMessenger<T>,
+    handlers: HashMap<String, Handler<'a, T>>,
+    inbox: ServerInbox,
+}
+
+impl<'a, T: Read + Write> RpcServer<'a, T> {
+
+    /// Initializes a new RpcServer with no handlers
+    pub fn new(messenger: DualMessenger<T>) -> RpcServer<'a, T> {
+        RpcServer {
+            messenger,
+            handlers: HashMap::new(),
+            inbox: ServerInbox {
+                requests: VecDeque::new(),
+                stream_frames: HashMap::new(),
+                active_stream: None,
+            },
+        }
+    }
+
+    /// Registers the handler that answers requests for a method, replacing any previous one
+    pub fn register<V, F>(&mut self, method: V, handler: F) -> &mut RpcServer<'a, T> where V: Into<String>, F: FnMut(Vec<u8>) -> Vec<u8> + 'a {
+        self.handlers.insert(method.into(), Handler::Unary(Box::new(handler)));
+        self
+    }
+
+    /// Registers the handler that answers requests for a method with a stream of items, replacing any previous one
+    ///
+    /// Returning `Ok` ends the stream normally; returning `Err` ends it with an error carrying the message.
+    /// Streaming needs a stream whose reads can time out, so the handler can check for a cancellation without blocking.
+    pub fn register_streaming<V, F>(&mut self, method: V, handler: F) -> &mut RpcServer<'a, T>
+        where V: Into<String>, F: FnMut(Vec<u8>, &mut ResponseStream<T>) -> result::Result<(), String> + 'a, T: ReadTimeout {
+        self.handlers.insert(method.into(), Handler::Streaming(Box::new(handler)));
+        self
+    }
+
+    /// Reads the next request, runs its handler and sends back the response
+    ///
+    /// Requests for a method without a handler are answered with an unknown-method response.
+    ///
+    /// # Errors
+    /// This method will return `BufferEmpty` if the stream ends before a new request begins.
+    /// This method will return `UnknownBoundary` for frames that are not requests; the stream stays in sync.
+    pub fn handle_next(&mut self) -> Result<()> {
+        let (id, method, request) = loop {
+            match self.inbox.requests.pop_front() {
+                Some(request) => break request,
+                None => self.inbox.receive(&mut self.messenger)?,
+            }
+        };
+
+        match self.handlers.get_mut(&method) {
+            Some(&mut Handler::Unary(ref mut handler)) => {
+                let response = handler(request);
+                self.inbox.stream_frames.remove(&id);
+                write_response(&mut self.messenger, id, RESPONSE_OK, &response)?;
+            }
+            Some(&mut Handler::Streaming(ref mut handler)) => {
+                self.inbox.active_stream = Some(id);
+                let (outcome, cancelled) = {
+                    let mut response_stream = ResponseStream {
+                        id,
+                        messenger: &mut self.messenger,
+                        inbox: &mut self.inbox,
+                        finished_receiving: false,
+                        cancelled: false,
+                    };
+                    let outcome = handler(request, &mut response_stream);
+                    (outcome, response_stream.seen_cancel())
+                };
+                self.inbox.active_stream = None;
+                self.inbox.stream_frames.remove(&id);
+                // a cancelled call has nobody left listening for its end
+                match outcome {
+                    _ if cancelled => {}
+                    Ok(()) => write_response(&mut self.messenger, id, RESPONSE_STREAM_END, &[])?,
+                    Err(message) => write_response(&mut self.messenger, id, RESPONSE_STREAM_ERROR, message.as_bytes())?,
+                }
+            }
+            None => write_response(&mut self.messenger, id, RESPONSE_UNKNOWN_METHOD, &[])?,
+        }
+        Ok(())
+    }
+
+    /// Handles requests until the stream runs out
+    ///
+    /// Frames that are not requests, and requests that do not decode, are skipped like the client's reader skips bad responses.
+    ///
+    /// # Errors
+    /// This method will return the first error the stream itself reports, other than a read timing out.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            match self.handle_next() {
+                Ok(()) => continue,
+                Err(e) => match *e.kind() {
+                    ErrorKind::BufferEmpty => return Ok(()),
+                    ErrorKind::IOError(_) if !e.is_read_timeout() => return Err(e),
+                    _ => continue,
+                },
+            }
+        }
+    }
+
+    /// Gives back the DualMessenger the server was answering on
+    pub fn release(self) -> DualMessenger<T> {
+        self.messenger
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+
+use super::frame_header::split_header_fields;
+use super::{Error, ErrorKind, Result};
+
+/// The most sequence numbers a reader remembers as missing, beyond which the oldest are forgotten
+///
+/// A forgotten frame that arrives late is reported as a duplicate rather than as out of order.
+const MAX_MISSING_SEQUENCES: usize = 1024;
+
+/// Checks the sequence numbers a reader receives against the ones it expects
+///
+/// A frame that reveals a problem but still carries a new message keeps the message back,
+/// so the error is returned first and the message is returned by the following read.
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTracker {
+    next_expected: u64,
+    missing: BTreeSet<u64>,
+    held_back: Option<Vec<u8>>,
+}
+
+impl SequenceTracker {
+
+    /// Returns a message held back by the previous read, if there is one
+    pub(crate) fn take_held_back(&mut self) -> Option<Vec<u8>> {
+        self.held_back.take()
+    }
+
+    /// Splits the sequence number off a payload and checks it
+    pub(crate) fn accept(&mut self, delimiter_string: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let (fields, message) = split_header_fields(delimiter_string, 1, payload)?;
+        let received = fields[0];
+
+        if received == self.next_expected {
+            self.next_expected += 1;
+            Ok(message)
+        } else if received > self.next_expected {
+            let expected = self.next_expected;
+            self.missing.extend(expected.max(received.saturating_sub(MAX_MISSING_SEQUENCES as u64))..received);
+            while self.missing.len() > MAX_MISSING_SEQUENCES {
+                let oldest = *self.missing.iter().next().expect("the set is not empty");
+                self.missing.remove(&oldest);
+            }
+            self.next_expected = received.saturating_add(1);
+            self.held_back = Some(message);
+            Err(Error::from(ErrorKind::SequenceGap { expected, received }))
+        } else if self.missing.remove(&received) {
+            self.held_back = Some(message);
+            Err(Error::from(ErrorKind::OutOfOrderSequence(received)))
+        } else {
+            Err(Error::from(ErrorKind::DuplicateSequence(received)))
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::{Read, Write};
+
+use super::frame_header::{prepend_header_fields, split_header_fields};
+use super::replay::{ReplayBuffer, ACK_FRAME, DATA_FRAME};
+use super::{DualMessenger, Error, ErrorKind, Result};
+
+/// What one side of a session sends first on a new connection
+///
+/// See `SessionMessenger::read_resume`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResumeRequest {
+    session_id: u64,
+    next_expected: u64,
+}
+
+impl ResumeRequest {
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Returns the sequence number of the first message the peer has not received
+    pub fn next_expected(&self) -> u64 {
+        self.next_expected
+    }
+}
+
+/// A conversation that carries on across connections without losing or repeating messages
+///
+/// Every message is sent with a sequence number and kept in a replay buffer until the peer acknowledges it.
+/// When a connection is lost, both sides move to a new one with `resume`, telling each other the first message they
+/// have not received, and the messages the other side is missing are sent again. Messages that were already received
+/// are never delivered twice.
+///
+/// At most `replay_capacity` messages are kept unacknowledged; sending more waits for acknowledgements first.
+/// Both ends of the stream must use a SessionMessenger with the same session ID.
+///
+/// A SessionMessenger does not connect by itself. To reconnect with a Backoff, take every connection from a
+/// ReconnectingMessenger with `take_messenger`, and `resume` on the next one when `send` or `receive` fails.
+pub struct SessionMessenger<T> where T: Read + Write {
+    session_id: u64,
+    messenger: DualMessenger<T>,
+    replay_capacity: usize,
+    resume_boundaries: (String, String),
+    replay: ReplayBuffer,
+}
+
+impl<T: Read + Write> SessionMessenger<T> {
+
+    /// Starts a new session on a DualMessenger
+    ///
+    /// Data frames are written between the DualMessenger's boundaries; acknowledgements and resumption use reserved
+    /// control boundaries. `replay_capacity` is raised to 1 if it is smaller, so a message can always be sent.
+    pub fn new(session_id: u64, messenger: DualMessenger<T>, replay_capacity: usize) -> SessionMessenger<T> {
+        let resume_boundaries = messenger.configuration().control_boundaries("resume");
+        let replay = ReplayBuffer::new(messenger.configuration());
+        SessionMessenger {
+            session_id,
+            messenger,
+            replay_capacity: replay_capacity.max(1),
+            resume_boundaries,
+            replay,
+        }
+    }
+
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Returns how many sent messages are kept until the peer acknowledges them
+    pub fn unacknowledged(&self) -> usize {
+        self.replay.unacknowledged()
+    }
+
+    /// Sends a message, waiting for acknowledgements first if the replay buffer is full
+    ///
+    /// Messages that arrive while waiting are kept for `receive`.
+    ///
+    /// # Errors
+    /// This method will return Err if the stream fails while waiting or writing.
+    /// A message whose write failed is still in the replay buffer, and `resume` sends it.
+    pub fn send(&mut self, message: &[u8]) -> Result<()> {
+        while self.replay.unacknowledged() >= self.replay_capacity {
+            self.process_next_frame()?;
+        }
+        let payload = self.replay.number(&self.messenger.configuration().delimiter_string, message);
+        self.replay.keep(payload);
+        self.replay.write_newest(&mut self.messenger)
+    }
+
+    /// Receives the next message in the order it was sent
+    ///
+    /// # Errors
+    /// This method will return `BufferEmpty` if the stream ends before a message arrives; the session can then be resumed.
+    /// This method will return `SequenceGap` if a message arrives ahead of one that never did.
+    pub fn receive(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(message) = self.replay.take_delivered() {
+                return Ok(message);
+            }
+            self.process_next_frame()?;
+        }
+    }
+
+    /// Moves the session to a new connection, as the side that speaks first
+    ///
+    /// This side's resume frame is written before the peer's is read, so the peer should call `read_resume`
+    /// and `resume_from` on its end.
+    ///
+    /// # Errors
+    /// This method fails like `resume_from`, or if the peer's resume frame cannot be read.
+    pub fn resume(&mut self, mut messenger: DualMessenger<T>) -> Result<()> {
+        self.write_resume(&mut messenger)?;
+        let request = SessionMessenger::read_resume(&mut messenger)?;
+        self.check_session(request)?;
+        self.replace_connection(messenger, request)
+    }
+
+    /// Reads the resume frame a peer sends first on a new connection
+    ///
+    /// Its session ID tells which SessionMessenger the connection belongs to; that one then takes it with `resume_from`.
+    ///
+    /// # Errors
+    /// This method will return `UnknownBoundary` if the first frame is not a resume frame.
+    pub fn read_resume(messenger: &mut DualMessenger<T>) -> Result<ResumeRequest> {
+        let (resume_beg, resume_end) = messenger.configuration().control_boundaries("resume");
+        let (_, payload) = messenger.read_next_tagged_message(&[(resume_beg.as_str(), resume_end.as_str())])?;
+        let (fields, _) = split_header_fields(&messenger.configuration().delimiter_string, 2, payload)?;
+        Ok(ResumeRequest {
+            session_id: fields[0],
+            next_expected: fields[1],
+        })
+    }
+
+    /// Moves the session to a new connection whose resume frame was read with `read_resume`
+    ///
+    /// This side's resume frame is written back, and then every message the peer is missing is sent again.
+    ///
+    /// # Errors
+    /// This method will return `UnknownSession` if the request is for a different session.
+    /// This method will return `SequenceGap` if the peer is missing messages that have already left the replay buffer.
+    pub fn resume_from(&mut self, mut messenger: DualMessenger<T>, request: ResumeRequest) -> Result<()> {
+        self.check_session(request)?;
+        self.write_resume(&mut messenger)?;
+        self.replace_connection(messenger, request)
+    }
+
+    pub fn release(self) -> DualMessenger<T> {
+        self.messenger
+    }
+
+    fn write_resume(&self, messenger: &mut DualMessenger<T>) -> Result<()> {
+        let payload = prepend_header_fields(&messenger.configuration().delimiter_string, &[self.session_id, self.replay.next_expected()], &[]);
+        messenger.write_between(&self.resume_boundaries.0, &self.resume_boundaries.1, &payload)?;
+        messenger.flush()?;
+        Ok(())
+    }
+
+    fn check_session(&self, request: ResumeRequest) -> Result<()> {
+        if request.session_id != self.session_id {
+            return Err(Error::from(ErrorKind::UnknownSession(request.session_id)));
+        }
+        Ok(())
+    }
+
+    fn replace_connection(&mut self, messenger: DualMessenger<T>, request: ResumeRequest) -> Result<()> {
+        self.replay.acknowledge(request.next_expected);
+        // anything older than the replay buffer was acknowledged once, so a peer asking for it has lost its own state
+        let oldest = self.replay.oldest_sequence();
+        if request.next_expected < oldest {
+            return Err(Error::from(ErrorKind::SequenceGap { expected: request.next_expected, received: oldest }));
+        }
+        self.messenger = messenger;
+        self.replay.resend_all(&mut self.messenger)
+    }
+
+    fn process_next_frame(&mut self) -> Result<()> {
+        let (index, payload) = match self.replay.read_frame(&mut self.messenger, &[])? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let (fields, message) = split_header_fields(&self.messenger.configuration().delimiter_string, 1, payload)?;
+        match index {
+            DATA_FRAME => {
+                let next_expected = self.replay.next_expected();
+                if fields[0] > next_expected {
+                    return Err(Error::from(ErrorKind::SequenceGap { expected: next_expected, received: fields[0] }));
+                }
+                // a message sent again after a resume that had already arrived is only acknowledged
+                self.replay.accept(fields[0], message);
+                self.replay.write_ack(&mut self.messenger)
+            }
+            ACK_FRAME => {
+                self.replay.acknowledge(fields[0]);
+                Ok(())
+            }
+            _ => unreachable!("only two kinds of frame are read"),
+        }
+    }
+}
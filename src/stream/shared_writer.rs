@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{Message, MessageWriter};
+
+/// A MessageWriter that many threads can send through at once
+///
+/// Clones share one MessageWriter behind a lock. Each frame is written while holding it,
+/// so frames from different senders never interleave on the stream.
+#[derive(Debug)]
+pub struct SharedMessageWriter<W> where W: Write {
+    writer: Arc<Mutex<MessageWriter<W>>>,
+}
+
+impl<W: Write> Clone for SharedMessageWriter<W> {
+    fn clone(&self) -> SharedMessageWriter<W> {
+        SharedMessageWriter {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl<W: Write> SharedMessageWriter<W> {
+
+    /// Initializes a new SharedMessageWriter around a MessageWriter
+    pub fn new(writer: MessageWriter<W>) -> SharedMessageWriter<W> {
+        SharedMessageWriter {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MessageWriter<W>> {
+        self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Writes a message and flushes it, returning the number of bytes framed
+    pub fn send(&self, message: &[u8]) -> io::Result<usize> {
+        let mut writer = self.lock();
+        let written = writer.write(message)?;
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Writes a typed message between the boundaries of its variant and flushes it
+    pub fn send_message<M: Message>(&self, message: &M) -> io::Result<usize> {
+        let mut writer = self.lock();
+        let written = writer.send_message(message)?;
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Writes a message between the given boundaries and flushes it
+    ///
+    /// See `MessageWriter::write_between`.
+    pub fn write_between(&self, beg_bound: &str, end_bound: &str, buf: &[u8]) -> io::Result<usize> {
+        let mut writer = self.lock();
+        let written = writer.write_between(beg_bound, end_bound, buf)?;
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Writes an error frame and flushes it
+    ///
+    /// See `MessageWriter::send_error`.
+    pub fn send_error(&self, code: u64, message: &str) -> io::Result<usize> {
+        let mut writer = self.lock();
+        let written = writer.send_error(code, message)?;
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Stops sending for every clone, telling the peer with a close frame
+    ///
+    /// See `DualMessenger::close`.
+    pub fn close(&self, reason: Option<u64>) -> io::Result<()> {
+        self.lock().close(reason)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().is_closed()
+    }
+
+    /// Returns the MessageWriter if this is the last clone, or gives this clone back otherwise
+    pub fn try_into_inner(self) -> Result<MessageWriter<W>, SharedMessageWriter<W>> {
+        match Arc::try_unwrap(self.writer) {
+            Ok(writer) => Ok(writer.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())),
+            Err(writer) => Err(SharedMessageWriter { writer }),
+        }
+    }
+}
+
+impl<W: Write> Write for SharedMessageWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
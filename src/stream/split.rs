@@ -0,0 +1,105 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::{ReadTimeout, WriteTimeout};
+
+/// Streams that can hand out a second handle to themselves, so reading and writing need no lock
+pub trait TryCloneStream: Sized {
+    fn try_clone_stream(&self) -> io::Result<Self>;
+}
+
+impl TryCloneStream for TcpStream {
+    fn try_clone_stream(&self) -> io::Result<TcpStream> {
+        self.try_clone()
+    }
+}
+
+#[cfg(unix)]
+impl TryCloneStream for UnixStream {
+    fn try_clone_stream(&self) -> io::Result<UnixStream> {
+        self.try_clone()
+    }
+}
+
+static NEXT_SPLIT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a token no other split has been given, which marks the two halves of one split as belonging together
+pub(crate) fn next_split_token() -> u64 {
+    NEXT_SPLIT_TOKEN.fetch_add(1, Ordering::Relaxed)
+}
+
+fn lock<T>(stream: &Mutex<Box<T>>) -> MutexGuard<'_, Box<T>> {
+    stream.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The reading half of a stream shared with a WriteHalf
+///
+/// The stream is locked for each call to `read`, so a read that blocks also holds up the WriteHalf until it returns.
+#[derive(Debug)]
+pub struct ReadHalf<T> {
+    stream: Arc<Mutex<Box<T>>>,
+}
+
+/// The writing half of a stream shared with a ReadHalf
+#[derive(Debug)]
+pub struct WriteHalf<T> {
+    stream: Arc<Mutex<Box<T>>>,
+}
+
+pub(crate) fn halves<T>(stream: Box<T>) -> (ReadHalf<T>, WriteHalf<T>) {
+    let stream = Arc::new(Mutex::new(stream));
+    (ReadHalf { stream: stream.clone() }, WriteHalf { stream })
+}
+
+/// Puts the stream back together, or returns the halves if they came from different streams
+pub(crate) fn unsplit<T>(read_half: ReadHalf<T>, write_half: WriteHalf<T>) -> Result<Box<T>, (ReadHalf<T>, WriteHalf<T>)> {
+    if !Arc::ptr_eq(&read_half.stream, &write_half.stream) {
+        return Err((read_half, write_half));
+    }
+    drop(write_half);
+    match Arc::try_unwrap(read_half.stream) {
+        Ok(stream) => Ok(stream.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())),
+        Err(_) => unreachable!("only the two halves share the stream"),
+    }
+}
+
+impl<T: Read> Read for ReadHalf<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        lock(&self.stream).read(buf)
+    }
+}
+
+impl<T: Write> Write for WriteHalf<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lock(&self.stream).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        lock(&self.stream).flush()
+    }
+}
+
+impl<T: ReadTimeout> ReadTimeout for ReadHalf<T> {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        lock(&self.stream).read_timeout()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        lock(&self.stream).set_read_timeout(timeout)
+    }
+}
+
+impl<T: WriteTimeout> WriteTimeout for WriteHalf<T> {
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        lock(&self.stream).write_timeout()
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        lock(&self.stream).set_write_timeout(timeout)
+    }
+}
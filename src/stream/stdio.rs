@@ -0,0 +1,154 @@
+use std::io::{self, Read, StdinLock, Write};
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(unix)]
+use std::os::raw::c_int;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg(not(unix))]
+use std::io::StdoutLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{DualMessenger, StreamConfiguration};
+
+/// Set once stdout has been taken over for frames, which can only happen once per process
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+const STDOUT_FD: c_int = 1;
+#[cfg(unix)]
+const STDERR_FD: c_int = 2;
+
+#[cfg(unix)]
+extern "C" {
+    fn dup(fd: c_int) -> c_int;
+    fn dup2(src: c_int, dst: c_int) -> c_int;
+}
+
+/// A separate reader and writer used together as one stream
+#[derive(Debug)]
+pub struct JoinedStream<R, W> where R: Read, W: Write {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> JoinedStream<R, W> {
+    pub fn new(reader: R, writer: W) -> JoinedStream<R, W> {
+        JoinedStream {
+            reader,
+            writer,
+        }
+    }
+
+    pub fn get_reader(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_writer(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: Read, W: Write> Read for JoinedStream<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: Read, W: Write> Write for JoinedStream<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The process's stdout, reserved for frames by `DualMessenger::stdio`
+///
+/// On unix this is a duplicate of the original stdout, while `print!` and friends are sent to stderr instead.
+/// Elsewhere it holds the stdout lock, so other threads' prints wait rather than land inside a frame.
+/// That lock is reentrant, though, so a `print!` on the thread holding it still goes into the frame stream;
+/// use `eprint!` there instead.
+#[derive(Debug)]
+pub struct StdoutFrames {
+    #[cfg(unix)]
+    stdout: File,
+    #[cfg(not(unix))]
+    stdout: StdoutLock<'static>,
+}
+
+impl StdoutFrames {
+    #[cfg(unix)]
+    fn take() -> io::Result<StdoutFrames> {
+        io::stdout().flush()?;
+        // the duplicate keeps the real stdout for frames once fd 1 is pointed at stderr
+        let fd = unsafe { dup(STDOUT_FD) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stdout = unsafe { File::from_raw_fd(fd) };
+        if unsafe { dup2(STDERR_FD, STDOUT_FD) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(StdoutFrames { stdout })
+    }
+
+    #[cfg(not(unix))]
+    fn take() -> io::Result<StdoutFrames> {
+        let mut stdout = io::stdout().lock();
+        stdout.flush()?;
+        Ok(StdoutFrames { stdout })
+    }
+}
+
+impl Write for StdoutFrames {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+/// A DualMessenger over the process's own stdin and stdout
+pub type StdioMessenger = DualMessenger<JoinedStream<StdinLock<'static>, StdoutFrames>>;
+
+impl<R: Read, W: Write> DualMessenger<JoinedStream<R, W>> {
+
+    /// Initializes a new DualMessenger that reads from `reader` and writes to `writer`
+    pub fn from_halves(config: StreamConfiguration, reader: R, writer: W) -> DualMessenger<JoinedStream<R, W>> {
+        DualMessenger::new_from_config(config, JoinedStream::new(reader, writer))
+    }
+}
+
+impl DualMessenger<JoinedStream<StdinLock<'static>, StdoutFrames>> {
+
+    /// Initializes a new DualMessenger over this process's stdin and stdout, such as in a child started by a ProcessMessenger
+    ///
+    /// Stdin stays locked for the life of the messenger. Stdout is kept for frames alone: on unix anything printed
+    /// afterwards goes to stderr, and elsewhere stdout stays locked so other threads cannot print into a frame.
+    /// Outside unix, printing to stdout from the thread that made the messenger still corrupts the frame stream.
+    ///
+    /// # Errors
+    /// This method will return Err if stdout has already been taken by an earlier call, or cannot be duplicated.
+    pub fn stdio(config: StreamConfiguration) -> io::Result<StdioMessenger> {
+        if STDOUT_TAKEN.swap(true, Ordering::SeqCst) {
+            return Err(io::Error::other("stdout is already carrying frames"));
+        }
+        let stdout = match StdoutFrames::take() {
+            Ok(v) => v,
+            Err(e) => {
+                STDOUT_TAKEN.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        Ok(DualMessenger::from_halves(config, io::stdin().lock(), stdout))
+    }
+}
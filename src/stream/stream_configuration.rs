@@ -4,16 +4,55 @@ pub struct StreamConfiguration {
     pub(crate) beginning_boundary: String,
     pub(crate) ending_boundary: String,
     pub(crate) hashing_enabled: bool,
+    pub(crate) sequence_numbers_enabled: bool,
 }
 
 impl StreamConfiguration {
+    /// Creates a new StreamConfiguration without sequence numbers
+    ///
+    /// With hashing enabled, every message written through `write` carries a SHA3-256 digest that `read_next_message` checks.
+    /// Only those messages are hashed: frames written between other boundaries, such as with `write_between`,
+    /// `send_message` or `send_error`, carry their payload as it is. Both ends of a stream must agree on this setting.
     pub fn new<T: Into<String>>(delimiter_string: T, beginning_boundary: T, ending_boundary: T, hashing_enabled: bool) -> StreamConfiguration {
         StreamConfiguration {
             delimiter_string: delimiter_string.into(),
             beginning_boundary: beginning_boundary.into(),
             ending_boundary: ending_boundary.into(),
-            hashing_enabled: hashing_enabled,
+            hashing_enabled,
+            sequence_numbers_enabled: false,
         }
     }
 
+    /// Returns a copy of this configuration with sequence numbers turned on or off
+    ///
+    /// With sequence numbers on, every message written through `write` carries the next number in a header field,
+    /// and reading checks them so lost, replayed and reordered messages are reported as errors.
+    /// Like hashing, they only apply to those messages. Both ends of a stream must agree on this setting.
+    pub fn with_sequence_numbers(self, enabled: bool) -> StreamConfiguration {
+        StreamConfiguration {
+            sequence_numbers_enabled: enabled,
+            ..self
+        }
+    }
+
+    pub fn sequence_numbers_enabled(&self) -> bool {
+        self.sequence_numbers_enabled
+    }
+
+    /// Returns a copy of this configuration that writes between a different pair of boundaries
+    pub(crate) fn with_boundaries<T: Into<String>>(&self, beginning_boundary: T, ending_boundary: T) -> StreamConfiguration {
+        StreamConfiguration {
+            beginning_boundary: beginning_boundary.into(),
+            ending_boundary: ending_boundary.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the `(beginning, ending)` boundaries reserved for the named control frame
+    ///
+    /// Control boundaries extend the configured ones with `!` and the frame's name, so they never collide with a message length.
+    pub(crate) fn control_boundaries(&self, name: &str) -> (String, String) {
+        (format!("{}!{}", self.beginning_boundary, name), format!("{}!{}", self.ending_boundary, name))
+    }
+
 }
\ No newline at end of file
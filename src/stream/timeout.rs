@@ -0,0 +1,140 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+/// Streams whose reads can be made to give up after a while
+pub trait ReadTimeout {
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl ReadTimeout for UnixStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        UnixStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Streams whose writes can be made to give up after a while
+pub trait WriteTimeout {
+    fn write_timeout(&self) -> io::Result<Option<Duration>>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl WriteTimeout for TcpStream {
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::write_timeout(self)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl WriteTimeout for UnixStream {
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        UnixStream::write_timeout(self)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+fn remaining_until(deadline: Instant) -> io::Result<Duration> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining == Duration::from_secs(0) {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "the deadline has passed"));
+    }
+    Ok(remaining)
+}
+
+/// Shortens the stream's read timeout before every read so that no read runs past the deadline
+///
+/// The read timeout the stream had before is put back when this is dropped.
+pub(crate) struct DeadlineReader<'a, T: 'a> where T: Read + ReadTimeout {
+    reader: &'a mut T,
+    deadline: Instant,
+    previous_timeout: Option<Duration>,
+}
+
+impl<'a, T: Read + ReadTimeout> DeadlineReader<'a, T> {
+    pub(crate) fn new(reader: &'a mut T, deadline: Instant) -> io::Result<DeadlineReader<'a, T>> {
+        let previous_timeout = reader.read_timeout()?;
+        Ok(DeadlineReader {
+            reader,
+            deadline,
+            previous_timeout,
+        })
+    }
+}
+
+impl<'a, T: Read + ReadTimeout> Read for DeadlineReader<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.set_read_timeout(Some(remaining_until(self.deadline)?))?;
+        self.reader.read(buf)
+    }
+}
+
+impl<'a, T: Read + ReadTimeout> Drop for DeadlineReader<'a, T> {
+    fn drop(&mut self) {
+        // there is nowhere to report a failure from here, and the stream is still usable either way
+        let _ = self.reader.set_read_timeout(self.previous_timeout);
+    }
+}
+
+/// Shortens the stream's write timeout before every write so that no write runs past the deadline
+///
+/// The write timeout the stream had before is put back when this is dropped.
+pub(crate) struct DeadlineWriter<'a, T: 'a> where T: Write + WriteTimeout {
+    writer: &'a mut T,
+    deadline: Instant,
+    previous_timeout: Option<Duration>,
+}
+
+impl<'a, T: Write + WriteTimeout> DeadlineWriter<'a, T> {
+    pub(crate) fn new(writer: &'a mut T, deadline: Instant) -> io::Result<DeadlineWriter<'a, T>> {
+        let previous_timeout = writer.write_timeout()?;
+        Ok(DeadlineWriter {
+            writer,
+            deadline,
+            previous_timeout,
+        })
+    }
+}
+
+impl<'a, T: Write + WriteTimeout> Write for DeadlineWriter<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.set_write_timeout(Some(remaining_until(self.deadline)?))?;
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.set_write_timeout(Some(remaining_until(self.deadline)?))?;
+        self.writer.flush()
+    }
+}
+
+impl<'a, T: Write + WriteTimeout> Drop for DeadlineWriter<'a, T> {
+    fn drop(&mut self) {
+        // as with DeadlineReader, the stream is still usable if this fails
+        let _ = self.writer.set_write_timeout(self.previous_timeout);
+    }
+}
@@ -1,45 +1,92 @@
-use std::io::{Write, Result};
+use std::io::{self, Write, Result};
 use std::mem;
+use std::time::{Duration, Instant};
 use super::stream_configuration::StreamConfiguration;
+use super::frame_header::{encode_message, encode_remote_error, prepend_header_fields};
+use super::timeout::DeadlineWriter;
+use super::{Error, ErrorKind, Message, WriteTimeout};
 
-pub(crate) struct InternalMessageWriter<'a, T: 'a> where T: Write {
-    internal_writer: &'a mut T,
-    temporary_configuration: &'a StreamConfiguration,
+/// Lays out a whole frame: the delimited beginning boundary and length, the message and the delimited ending boundary
+pub(crate) fn build_frame(configuration: &StreamConfiguration, buf: &[u8]) -> Vec<u8> {
+    let delimiter = configuration.delimiter_string.as_bytes();
+    let mut frame = Vec::with_capacity(buf.len() + 64);
+    frame.extend_from_slice(delimiter);
+    frame.extend_from_slice(configuration.beginning_boundary.as_bytes());
+    frame.extend_from_slice(mem::size_of_val(buf).to_string().as_bytes());
+    frame.extend_from_slice(delimiter);
+    frame.extend_from_slice(buf);
+    frame.extend_from_slice(delimiter);
+    frame.extend_from_slice(configuration.ending_boundary.as_bytes());
+    frame.extend_from_slice(delimiter);
+    frame
 }
 
-impl<'a, T: Write> InternalMessageWriter<'a, T> {
-    pub(crate) fn new(config: &'a StreamConfiguration, writer: &'a mut T) -> InternalMessageWriter<'a, T> {
-        InternalMessageWriter {
-            internal_writer: writer,
-            temporary_configuration: config
-        }
+/// Lays out the frame of a data message, with the sequence number and digest the configuration asks for
+pub(crate) fn build_message_frame(configuration: &StreamConfiguration, next_sequence: &mut u64, buf: &[u8]) -> Vec<u8> {
+    if !configuration.sequence_numbers_enabled && !configuration.hashing_enabled {
+        build_frame(configuration, buf)
+    } else {
+        build_frame(configuration, &encode_message(configuration, next_sequence, buf))
     }
 }
 
-impl<'a, T: Write> Write for InternalMessageWriter<'a, T> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let mut vec = Vec::new();
-        vec.push(self.internal_writer.write(self.temporary_configuration.delimiter_string.as_ref())?);
-        vec.push(self.internal_writer.write(self.temporary_configuration.beginning_boundary.as_ref())?);
-        vec.push(self.internal_writer.write(mem::size_of_val(buf).to_string().as_ref())?);
-        vec.push(self.internal_writer.write(self.temporary_configuration.delimiter_string.as_ref())?);
-        vec.push(self.internal_writer.write(buf)?);
-        vec.push(self.internal_writer.write(self.temporary_configuration.delimiter_string.as_ref())?);
-        vec.push(self.internal_writer.write(self.temporary_configuration.ending_boundary.as_ref())?);
-        vec.push(self.internal_writer.write(self.temporary_configuration.delimiter_string.as_ref())?);
-
-        Ok(vec.into_iter().fold(0, |x, y| x + y))
-
-    }
+/// Writes as much of `pending` as the writer takes, removing it, and returns how many bytes went out
+pub(crate) fn write_pending(writer: &mut dyn Write, pending: &mut Vec<u8>) -> (usize, Result<()>) {
+    let mut written = 0;
+    let result = loop {
+        if written == pending.len() {
+            break Ok(());
+        }
+        match writer.write(&pending[written..]) {
+            Ok(0) => break Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(count) => written += count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+    pending.drain(..written);
+    (written, result)
+}
 
-    fn flush(&mut self) -> Result<()> {
-        self.internal_writer.flush()
+/// Writes a whole frame through `pending`, keeping the part that did not go out if the writer fails partway through it
+///
+/// A frame that failed before any of it was written is dropped instead, so the write can simply be tried again.
+pub(crate) fn write_frame(writer: &mut dyn Write, pending: &mut Vec<u8>, frame: Vec<u8>) -> Result<usize> {
+    let frame_size = frame.len();
+    *pending = frame;
+    match write_pending(writer, pending) {
+        (_, Ok(())) => Ok(frame_size),
+        (written, Err(e)) => {
+            if written == 0 {
+                pending.clear();
+            }
+            Err(e)
+        }
     }
 }
 
+#[derive(Debug)]
 pub struct MessageWriter<T> where T: Write {
     configuration: StreamConfiguration,
     writer: T,
+    next_sequence: u64,
+    closed: bool,
+    pending: Vec<u8>,
+    // shared with the MessageReader made by the same `try_split`
+    split_token: Option<u64>,
+}
+
+/// Writes a data message through `pending` like `write_frame`, taking the next sequence number for it
+///
+/// If none of the frame went out, the sequence number is handed back for the retry.
+pub(crate) fn write_message(configuration: &StreamConfiguration, writer: &mut dyn Write, pending: &mut Vec<u8>, next_sequence: &mut u64, buf: &[u8]) -> Result<usize> {
+    let sequence = *next_sequence;
+    let frame = build_message_frame(configuration, next_sequence, buf);
+    let result = write_frame(writer, pending, frame);
+    if result.is_err() && pending.is_empty() {
+        *next_sequence = sequence;
+    }
+    result
 }
 
 impl<T: Write> MessageWriter<T> {
@@ -55,30 +102,197 @@ impl<T: Write> MessageWriter<T> {
                 end_bound.into(),
                 hashing_enabled
             ),
-            writer: writer
+            writer,
+            next_sequence: 0,
+            closed: false,
+            pending: Vec::new(),
+            split_token: None,
         }
     }
 
     pub fn new_from_config(config: StreamConfiguration, writer: T) -> MessageWriter<T> {
         MessageWriter {
             configuration: config,
-            writer: writer,
+            writer,
+            next_sequence: 0,
+            closed: false,
+            pending: Vec::new(),
+            split_token: None,
         }
     }
 
     pub fn get_writer(&self) -> &T {
         &self.writer
     }
+
+    pub(crate) fn configuration(&self) -> &StreamConfiguration {
+        &self.configuration
+    }
+
+    /// Writes a message between the given boundaries instead of the configured ones
+    ///
+    /// The delimiter still comes from this MessageWriter's configuration, but the frame is neither hashed nor numbered,
+    /// whatever the configuration says. This allows several kinds of message to be interleaved on one stream.
+    pub fn write_between(&mut self, beg_bound: &str, end_bound: &str, buf: &[u8]) -> Result<usize> {
+        self.check_open()?;
+        self.finish_pending()?;
+        let frame = build_frame(&self.configuration.with_boundaries(beg_bound, end_bound), buf);
+        write_frame(&mut self.writer, &mut self.pending, frame)
+    }
+
+    /// Writes an error frame carrying a code and a message in place of a message
+    ///
+    /// The reading side reports it as a `Remote` error, distinct from any error of its own.
+    /// Like `write_between`, the frame is not hashed.
+    pub fn send_error(&mut self, code: u64, message: &str) -> Result<usize> {
+        let (error_beg, error_end) = self.configuration.control_boundaries("error");
+        let payload = encode_remote_error(&self.configuration.delimiter_string, code, message);
+        self.write_between(&error_beg, &error_end, &payload)
+    }
+
+    /// Writes a typed message between the boundaries of its variant
+    ///
+    /// Like `write_between`, the frame is neither hashed nor numbered.
+    pub fn send_message<M: Message>(&mut self, message: &M) -> Result<usize> {
+        let (beginning_boundary, ending_boundary) = message.boundary();
+        self.write_between(beginning_boundary, ending_boundary, message.payload())
+    }
+
+    /// Stops sending, telling the peer with a close frame carrying an optional reason code
+    ///
+    /// See `DualMessenger::close`.
+    pub fn close(&mut self, reason: Option<u64>) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let (close_beg, close_end) = self.configuration.control_boundaries("close");
+        let payload = prepend_header_fields(&self.configuration.delimiter_string, &reason.into_iter().collect::<Vec<_>>(), &[]);
+        self.write_between(&close_beg, &close_end, &payload)?;
+        self.flush()?;
+        self.closed = true;
+        Ok(())
+    }
+
+    /// Returns whether this MessageWriter has closed the stream for sending
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Writes a message, giving up if the whole frame has not been written within `timeout`
+    ///
+    /// See `write_deadline`.
+    pub fn write_timeout(&mut self, buf: &[u8], timeout: Duration) -> super::Result<usize> where T: WriteTimeout {
+        self.write_deadline(buf, Instant::now() + timeout)
+    }
+
+    /// Writes a message and flushes it, giving up if the whole frame has not been written by `deadline`
+    ///
+    /// The deadline covers every write the frame takes, and the stream's own write timeout is put back once this returns.
+    ///
+    /// # Errors
+    /// This method will return `WriteTimedOut` if the deadline passes first, with the number of bytes this call wrote
+    /// and the number of bytes of the frame still to go. Those are kept and written ahead of the next frame or flush,
+    /// so the peer never sees half a frame followed by another; `wait_writable` finishes them with a deadline of its own.
+    pub fn write_deadline(&mut self, buf: &[u8], deadline: Instant) -> super::Result<usize> where T: WriteTimeout {
+        self.check_open()?;
+        self.write_frame_before(deadline, |configuration, next_sequence| build_message_frame(configuration, next_sequence, buf))
+    }
+
+    /// Writes a typed message between the boundaries of its variant, giving up if it takes longer than `timeout`
+    ///
+    /// Like `send_message`, the frame is neither hashed nor numbered. See `write_deadline`.
+    pub fn send_message_timeout<M: Message>(&mut self, message: &M, timeout: Duration) -> super::Result<usize> where T: WriteTimeout {
+        self.check_open()?;
+        let (beginning_boundary, ending_boundary) = message.boundary();
+        self.write_frame_before(Instant::now() + timeout, |configuration, _| {
+            build_frame(&configuration.with_boundaries(beginning_boundary, ending_boundary), message.payload())
+        })
+    }
+
+    /// Returns how many bytes of a timed out or interrupted frame are still waiting to be written
+    ///
+    /// While any are, the peer is not keeping up and the next write will have to wait for them first.
+    /// A `write` that fails partway through a frame, such as with `WouldBlock`, keeps the rest here too; `flush` sends it.
+    pub fn pending_bytes(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Writes the rest of a timed out frame, giving up if it has not all gone out within `timeout`
+    ///
+    /// Once this returns Ok, the next message does not have to wait behind an earlier one.
+    ///
+    /// # Errors
+    /// This method will return `WriteTimedOut` like `write_deadline` if the timeout passes first.
+    pub fn wait_writable(&mut self, timeout: Duration) -> super::Result<()> where T: WriteTimeout {
+        self.write_frame_before(Instant::now() + timeout, |_, _| Vec::new()).map(|_| ())
+    }
+
+    /// Queues the frame made by `build` behind any pending bytes and writes them all by `deadline`
+    ///
+    /// The frame is only built once the deadline is set on the stream, so a failure to set it takes no sequence number.
+    fn write_frame_before<F>(&mut self, deadline: Instant, build: F) -> super::Result<usize>
+        where T: WriteTimeout, F: FnOnce(&StreamConfiguration, &mut u64) -> Vec<u8> {
+        let mut writer = DeadlineWriter::new(&mut self.writer, deadline)?;
+        let frame = build(&self.configuration, &mut self.next_sequence);
+        let frame_size = frame.len();
+        self.pending.extend(frame);
+        match write_pending(&mut writer, &mut self.pending) {
+            (_, Ok(())) => {
+                writer.flush()?;
+                Ok(frame_size)
+            }
+            (written, Err(ref e)) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                Err(Error::from(ErrorKind::WriteTimedOut { written, remaining: self.pending.len() }))
+            }
+            (_, Err(e)) => Err(Error::from(e)),
+        }
+    }
+
+    fn finish_pending(&mut self) -> Result<()> {
+        write_pending(&mut self.writer, &mut self.pending).1
+    }
+
+    pub(crate) fn from_parts(configuration: StreamConfiguration, writer: T, next_sequence: u64, closed: bool, pending: Vec<u8>) -> MessageWriter<T> {
+        MessageWriter {
+            configuration,
+            writer,
+            next_sequence,
+            closed,
+            pending,
+            split_token: None,
+        }
+    }
+
+    pub(crate) fn split_token(&self) -> Option<u64> {
+        self.split_token
+    }
+
+    pub(crate) fn set_split_token(&mut self, token: u64) {
+        self.split_token = Some(token);
+    }
+
+    pub(crate) fn into_parts(self) -> (StreamConfiguration, T, u64, bool, Vec<u8>) {
+        (self.configuration, self.writer, self.next_sequence, self.closed, self.pending)
+    }
+
+    fn check_open(&self) -> Result<()> {
+        if self.closed {
+            return Err(io::Error::from(Error::from(ErrorKind::Closed { reason: None })));
+        }
+        Ok(())
+    }
 }
 
 impl<T: Write> Write for MessageWriter<T> {
     
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let mut temp_writer = InternalMessageWriter::new(&self.configuration, &mut self.writer);
-        Ok(temp_writer.write(buf)?)
+        self.check_open()?;
+        self.finish_pending()?;
+        write_message(&self.configuration, &mut self.writer, &mut self.pending, &mut self.next_sequence, buf)
     }
 
     fn flush(&mut self) -> Result<()> {
+        self.finish_pending()?;
         self.writer.flush()
     }
 
@@ -0,0 +1,225 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Cursor, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+use stream::{Error, ErrorKind, MessageReader, MessageWriter, StreamConfiguration};
+use super::faulty_io::SplitMix64;
+use super::{FaultSchedule, FaultyIo};
+
+/// How many cut points the truncation scenario tries
+const TRUNCATION_POINTS: usize = 32;
+
+/// How many streams the bit flip scenario corrupts
+const CORRUPTED_STREAMS: u64 = 16;
+
+/// A scenario of `check_conformance` that a configuration did not get through
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceFailure {
+    scenario: &'static str,
+    schedule: FaultSchedule,
+    reason: String,
+}
+
+impl ConformanceFailure {
+    pub fn scenario(&self) -> &'static str {
+        self.scenario
+    }
+
+    /// Returns the faults the scenario was run with, to replay it on a FaultyIo of your own
+    pub fn schedule(&self) -> FaultSchedule {
+        self.schedule
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for ConformanceFailure {
+    fn fmt(&self, fmter: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmter, "{} (seed {}): {}", self.scenario, self.schedule.seed, self.reason)
+    }
+}
+
+impl error::Error for ConformanceFailure {}
+
+type Outcome = ::std::result::Result<(), String>;
+
+/// Checks that messages written and read with `config` survive the faults of real transports
+///
+/// Each scenario writes a fixed set of messages through a MessageWriter and reads them back through a MessageReader,
+/// with a FaultyIo seeded from `seed` on either side:
+///
+/// * short reads, partial writes and `Interrupted` must not change what is read, and `Interrupted` must never surface
+/// * `WouldBlock` must be retryable on both sides without losing or repeating anything
+/// * a truncated stream must deliver every whole frame before the cut and then fail, rather than return a bad message
+/// * flipped bits must never cause a panic or a read that does not end; with hashing enabled,
+///   every message that is returned must also be one that was sent
+///
+/// # Errors
+/// This method will return the first scenario that failed, along with the schedule to reproduce it.
+pub fn check_conformance(config: &StreamConfiguration, seed: u64) -> ::std::result::Result<(), ConformanceFailure> {
+    let messages = sample_messages(config);
+    let clean = FaultSchedule::new(seed);
+    let scenarios: [(&'static str, FaultSchedule, FaultSchedule); 5] = [
+        ("short reads", clean, clean.with_short_reads(1.0)),
+        ("partial writes", clean.with_partial_writes(1.0), clean),
+        ("interrupted", clean.with_interrupts(0.3), clean.with_interrupts(0.3)),
+        ("would block", clean.with_would_blocks(0.3), clean.with_would_blocks(0.3)),
+        ("every transient fault", clean.with_partial_writes(0.5).with_interrupts(0.2).with_would_blocks(0.2),
+            clean.with_short_reads(0.5).with_interrupts(0.2).with_would_blocks(0.2)),
+    ];
+    for &(scenario, write_faults, read_faults) in &scenarios {
+        run(scenario, read_faults, || check_transient_faults(config, &messages, write_faults, read_faults))?;
+    }
+
+    let (stream, frame_ends) = write_messages(config, &messages, clean).map_err(|reason| ConformanceFailure {
+        scenario: "truncated",
+        schedule: clean,
+        reason,
+    })?;
+    for point in 0..TRUNCATION_POINTS {
+        let cut = cut_point(seed, point, stream.len());
+        let read_faults = clean.with_truncation(cut);
+        let complete = frame_ends.iter().filter(|&&end| end <= cut).count();
+        run("truncated", read_faults, || check_truncated(config, &messages, &stream, read_faults, complete))?;
+    }
+
+    for offset in 0..CORRUPTED_STREAMS {
+        let read_faults = FaultSchedule::new(seed.wrapping_add(offset)).with_bit_flips(0.05);
+        run("bit flips", read_faults, || check_bit_flips(config, &messages, &stream, read_faults))?;
+    }
+    Ok(())
+}
+
+/// Runs one scenario, turning a panic into a failure
+fn run<F>(scenario: &'static str, schedule: FaultSchedule, check: F) -> ::std::result::Result<(), ConformanceFailure> where F: FnOnce() -> Outcome {
+    let reason = match panic::catch_unwind(AssertUnwindSafe(check)) {
+        Ok(Ok(())) => return Ok(()),
+        Ok(Err(reason)) => reason,
+        Err(_) => String::from("panicked"),
+    };
+    Err(ConformanceFailure {
+        scenario,
+        schedule,
+        reason,
+    })
+}
+
+/// Messages that exercise empty payloads, every byte value, the frame's own markers and frames larger than a read
+fn sample_messages(config: &StreamConfiguration) -> Vec<Vec<u8>> {
+    let mut markers = Vec::new();
+    for marker in &[&config.delimiter_string, &config.beginning_boundary, &config.ending_boundary] {
+        markers.extend_from_slice(marker.as_bytes());
+        markers.extend_from_slice(config.delimiter_string.as_bytes());
+    }
+    vec![
+        Vec::new(),
+        Vec::from("hello, world!"),
+        markers,
+        (0..=255).collect(),
+        (0..10_000).map(|i| (i % 251) as u8).collect(),
+        Vec::from("goodbye"),
+    ]
+}
+
+/// Picks where a truncated stream ends, always including the very start and the last byte
+fn cut_point(seed: u64, point: usize, stream_size: usize) -> usize {
+    match point {
+        0 => 0,
+        1 => stream_size.saturating_sub(1),
+        _ => SplitMix64::new(seed.wrapping_add(point as u64)).below(stream_size.max(1)),
+    }
+}
+
+/// Writes every message, retrying around `WouldBlock`, and returns the stream along with where each frame ends
+fn write_messages(config: &StreamConfiguration, messages: &[Vec<u8>], faults: FaultSchedule) -> ::std::result::Result<(Vec<u8>, Vec<usize>), String> {
+    let mut writer = MessageWriter::new_from_config(config.clone(), FaultyIo::new(Vec::new(), faults));
+    let mut frame_ends = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        loop {
+            match writer.write(message) {
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if writer.pending_bytes() > 0 {
+                        flush(&mut writer).map_err(|e| format!("flushing message {} failed: {}", index, e))?;
+                        break;
+                    }
+                }
+                Err(e) => return Err(format!("writing message {} failed: {}", index, e)),
+            }
+        }
+        frame_ends.push(writer.get_writer().get_ref().len());
+    }
+    flush(&mut writer).map_err(|e| format!("the final flush failed: {}", e))?;
+    Ok((writer.get_writer().get_ref().clone(), frame_ends))
+}
+
+fn flush(writer: &mut MessageWriter<FaultyIo<Vec<u8>>>) -> io::Result<()> {
+    loop {
+        match writer.flush() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            result => return result,
+        }
+    }
+}
+
+/// Reads the next message, retrying reads that would block
+fn read_next(reader: &mut MessageReader<FaultyIo<Cursor<Vec<u8>>>>) -> ::std::result::Result<Vec<u8>, Error> {
+    loop {
+        match reader.read_next_message() {
+            Err(ref e) if e.is_read_timeout() => {}
+            result => return result,
+        }
+    }
+}
+
+fn check_transient_faults(config: &StreamConfiguration, messages: &[Vec<u8>], write_faults: FaultSchedule, read_faults: FaultSchedule) -> Outcome {
+    let (stream, _) = write_messages(config, messages, write_faults)?;
+    let mut reader = MessageReader::new_from_config(config.clone(), FaultyIo::new(Cursor::new(stream), read_faults));
+    for (index, message) in messages.iter().enumerate() {
+        match read_next(&mut reader) {
+            Ok(ref read) if read == message => {}
+            Ok(_) => return Err(format!("message {} was read back changed", index)),
+            Err(e) => return Err(format!("reading message {} failed: {}", index, e)),
+        }
+    }
+    match read_next(&mut reader) {
+        Err(ref e) if *e.kind() == ErrorKind::BufferEmpty => Ok(()),
+        Ok(_) => Err(String::from("a message was read that was never sent")),
+        Err(e) => Err(format!("the end of the stream was reported as {}", e)),
+    }
+}
+
+fn check_truncated(config: &StreamConfiguration, messages: &[Vec<u8>], stream: &[u8], read_faults: FaultSchedule, complete: usize) -> Outcome {
+    let mut reader = MessageReader::new_from_config(config.clone(), FaultyIo::new(Cursor::new(stream.to_vec()), read_faults));
+    for (index, message) in messages.iter().enumerate().take(complete) {
+        match read_next(&mut reader) {
+            Ok(ref read) if read == message => {}
+            Ok(_) => return Err(format!("message {} was read back changed", index)),
+            Err(e) => return Err(format!("reading message {}, which arrived whole, failed: {}", index, e)),
+        }
+    }
+    match read_next(&mut reader) {
+        Err(_) => Ok(()),
+        Ok(_) => Err(format!("a message was read from a stream cut after {} bytes", read_faults.truncate_after.unwrap_or(0))),
+    }
+}
+
+fn check_bit_flips(config: &StreamConfiguration, messages: &[Vec<u8>], stream: &[u8], read_faults: FaultSchedule) -> Outcome {
+    let mut reader = MessageReader::new_from_config(config.clone(), FaultyIo::new(Cursor::new(stream.to_vec()), read_faults));
+    // every read either returns a message or consumes at least a byte, so the stream runs out well within this
+    for _ in 0..stream.len() + messages.len() + 1 {
+        match read_next(&mut reader) {
+            Ok(ref read) if config.hashing_enabled && !messages.contains(read) => {
+                return Err(String::from("a corrupted message was returned despite hashing"));
+            }
+            Ok(_) => {}
+            Err(ref e) if *e.kind() == ErrorKind::BufferEmpty => return Ok(()),
+            Err(ref e) if matches!(*e.kind(), ErrorKind::Closed { .. }) => return Ok(()),
+            Err(_) => {}
+        }
+    }
+    Err(String::from("reading never reached the end of the stream"))
+}
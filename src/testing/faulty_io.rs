@@ -0,0 +1,214 @@
+use std::io::{self, Read, Write};
+
+/// Which faults a FaultyIo injects, and how often
+///
+/// Every rate is the chance, from 0 to 1, that a single call to `read` or `write` is hit by that fault.
+/// The same seed and rates always produce the same faults for the same sequence of calls, so a failing run can be replayed.
+/// A `WouldBlock` or `Interrupted` rate of 1 lets nothing through at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultSchedule {
+    pub(crate) seed: u64,
+    pub(crate) short_reads: f64,
+    pub(crate) partial_writes: f64,
+    pub(crate) interrupts: f64,
+    pub(crate) would_blocks: f64,
+    pub(crate) bit_flips: f64,
+    pub(crate) truncate_after: Option<usize>,
+}
+
+impl FaultSchedule {
+    /// Creates a new FaultSchedule that injects no faults until some are enabled
+    pub fn new(seed: u64) -> FaultSchedule {
+        FaultSchedule {
+            seed,
+            short_reads: 0.0,
+            partial_writes: 0.0,
+            interrupts: 0.0,
+            would_blocks: 0.0,
+            bit_flips: 0.0,
+            truncate_after: None,
+        }
+    }
+
+    /// Makes reads hand out a single byte, however much was asked for
+    pub fn with_short_reads(self, rate: f64) -> FaultSchedule {
+        FaultSchedule { short_reads: clamp_rate(rate), ..self }
+    }
+
+    /// Makes writes take only part of what they were given
+    pub fn with_partial_writes(self, rate: f64) -> FaultSchedule {
+        FaultSchedule { partial_writes: clamp_rate(rate), ..self }
+    }
+
+    /// Makes reads and writes fail with `Interrupted` before doing anything
+    pub fn with_interrupts(self, rate: f64) -> FaultSchedule {
+        FaultSchedule { interrupts: clamp_rate(rate), ..self }
+    }
+
+    /// Makes reads and writes fail with `WouldBlock` before doing anything
+    pub fn with_would_blocks(self, rate: f64) -> FaultSchedule {
+        FaultSchedule { would_blocks: clamp_rate(rate), ..self }
+    }
+
+    /// Makes reads flip one bit of the bytes they return
+    pub fn with_bit_flips(self, rate: f64) -> FaultSchedule {
+        FaultSchedule { bit_flips: clamp_rate(rate), ..self }
+    }
+
+    /// Ends the stream for reading once `bytes` have been read, as if the peer had gone away
+    pub fn with_truncation(self, bytes: usize) -> FaultSchedule {
+        FaultSchedule { truncate_after: Some(bytes), ..self }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for FaultSchedule {
+    /// A seed of 0 and no faults
+    fn default() -> FaultSchedule {
+        FaultSchedule::new(0)
+    }
+}
+
+fn clamp_rate(rate: f64) -> f64 {
+    if rate.is_nan() {
+        return 0.0;
+    }
+    rate.clamp(0.0, 1.0)
+}
+
+/// A small seeded generator, so faults are reproducible without pulling in a dependency
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+
+    /// Returns a number from 0 up to but not including `bound`, which must not be 0
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+/// Wraps a stream, injecting the faults real transports produce according to a FaultSchedule
+///
+/// Faults are only injected on the way through `read` and `write`; `flush` is passed straight to the stream.
+#[derive(Debug)]
+pub struct FaultyIo<T> {
+    inner: T,
+    schedule: FaultSchedule,
+    rng: SplitMix64,
+    bytes_read: usize,
+    faults_injected: usize,
+}
+
+impl<T> FaultyIo<T> {
+    pub fn new(inner: T, schedule: FaultSchedule) -> FaultyIo<T> {
+        FaultyIo {
+            inner,
+            schedule,
+            rng: SplitMix64::new(schedule.seed),
+            bytes_read: 0,
+            faults_injected: 0,
+        }
+    }
+
+    pub fn schedule(&self) -> FaultSchedule {
+        self.schedule
+    }
+
+    /// Returns how many faults have been injected so far
+    pub fn faults_injected(&self) -> usize {
+        self.faults_injected
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Fails the call with `Interrupted` or `WouldBlock` if the schedule says so
+    fn inject_error(&mut self) -> io::Result<()> {
+        if self.rng.chance(self.schedule.interrupts) {
+            self.faults_injected += 1;
+            return Err(io::Error::from(io::ErrorKind::Interrupted));
+        }
+        if self.rng.chance(self.schedule.would_blocks) {
+            self.faults_injected += 1;
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Read> Read for FaultyIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.inject_error()?;
+        let mut size = buf.len();
+        if let Some(truncate_after) = self.schedule.truncate_after {
+            size = size.min(truncate_after.saturating_sub(self.bytes_read));
+            if size == 0 {
+                return Ok(0);
+            }
+        }
+        if size > 1 && self.rng.chance(self.schedule.short_reads) {
+            self.faults_injected += 1;
+            size = 1;
+        }
+        let count = self.inner.read(&mut buf[..size])?;
+        self.bytes_read += count;
+        if count > 0 && self.rng.chance(self.schedule.bit_flips) {
+            self.faults_injected += 1;
+            let index = self.rng.below(count);
+            buf[index] ^= 1 << self.rng.below(8);
+        }
+        Ok(count)
+    }
+}
+
+impl<T: Write> Write for FaultyIo<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.inject_error()?;
+        let mut size = buf.len();
+        if size > 1 && self.rng.chance(self.schedule.partial_writes) {
+            self.faults_injected += 1;
+            size = 1 + self.rng.below(size - 1);
+        }
+        self.inner.write(&buf[..size])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,11 @@
+//! Tools for testing code built on messenger_plus against misbehaving transports and scripted peers
+//!
+//! Only available with the `testing` feature.
+
+mod faulty_io;
+mod conformance;
+mod scripted_peer;
+
+pub use self::faulty_io::*;
+pub use self::conformance::*;
+pub use self::scripted_peer::*;
@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+use std::thread;
+
+use stream::memory::{self, MemoryStream};
+use stream::{DualMessenger, Error, ErrorKind, StreamConfiguration};
+
+#[derive(Debug)]
+enum Step {
+    Expect(Vec<u8>),
+    ExpectBetween(String, String, Vec<u8>),
+    ExpectClose(Option<u64>),
+    Send(Vec<u8>),
+    SendBetween(String, String, Vec<u8>),
+    SendError(u64, String),
+}
+
+impl Step {
+    fn describe(&self) -> String {
+        match *self {
+            Step::Expect(ref message) => format!("expect \"{}\"", message.escape_ascii()),
+            Step::ExpectBetween(ref beg, ref end, ref message) => format!("expect \"{}\" between `{}` and `{}`", message.escape_ascii(), beg, end),
+            Step::ExpectClose(reason) => format!("expect a close frame with reason {:?}", reason),
+            Step::Send(ref message) => format!("send \"{}\"", message.escape_ascii()),
+            Step::SendBetween(ref beg, ref end, ref message) => format!("send \"{}\" between `{}` and `{}`", message.escape_ascii(), beg, end),
+            Step::SendError(code, ref message) => format!("send error {}: {}", code, message),
+        }
+    }
+}
+
+/// A mock transport that plays the other side of a conversation from a script
+///
+/// The script is a list of messages to expect from the code under test and messages to send back, in order:
+/// each run of sends is made available to read as soon as every expectation before it has been met.
+/// Frames are encoded and decoded with the given StreamConfiguration, so sequence numbers, hashing and control frames
+/// are handled as a DualMessenger would, and the script only deals in message payloads.
+///
+/// Writing a frame the script does not expect panics with the step that failed and where the frames differ.
+/// So does reading while the script is waiting for a frame, which would hang against a real peer,
+/// and dropping the ScriptedPeer before the script is finished. Once it is, reads report the end of the stream.
+#[derive(Debug)]
+pub struct ScriptedPeer {
+    stream: MemoryStream,
+    peer: DualMessenger<MemoryStream>,
+    steps: VecDeque<Step>,
+    steps_done: usize,
+}
+
+impl ScriptedPeer {
+    /// Creates a new ScriptedPeer with an empty script
+    pub fn new(config: StreamConfiguration) -> ScriptedPeer {
+        let (stream, peer) = memory::duplex();
+        stream.set_nonblocking(true);
+        peer.set_nonblocking(true);
+        ScriptedPeer {
+            stream,
+            peer: DualMessenger::new_from_config(config, peer),
+            steps: VecDeque::new(),
+            steps_done: 0,
+        }
+    }
+
+    /// Expects the code under test to write `message` between the configured boundaries
+    pub fn expect<M: Into<Vec<u8>>>(mut self, message: M) -> ScriptedPeer {
+        self.steps.push_back(Step::Expect(message.into()));
+        self
+    }
+
+    /// Expects the code under test to write `message` between the given boundaries, such as a typed message's
+    pub fn expect_between<V: Into<String>, M: Into<Vec<u8>>>(mut self, beg_bound: V, end_bound: V, message: M) -> ScriptedPeer {
+        self.steps.push_back(Step::ExpectBetween(beg_bound.into(), end_bound.into(), message.into()));
+        self
+    }
+
+    /// Expects the code under test to close the stream with the given reason
+    pub fn expect_close(mut self, reason: Option<u64>) -> ScriptedPeer {
+        self.steps.push_back(Step::ExpectClose(reason));
+        self
+    }
+
+    /// Sends `message` between the configured boundaries
+    pub fn send<M: Into<Vec<u8>>>(mut self, message: M) -> ScriptedPeer {
+        self.steps.push_back(Step::Send(message.into()));
+        self
+    }
+
+    /// Sends `message` between the given boundaries, such as a typed message's
+    pub fn send_between<V: Into<String>, M: Into<Vec<u8>>>(mut self, beg_bound: V, end_bound: V, message: M) -> ScriptedPeer {
+        self.steps.push_back(Step::SendBetween(beg_bound.into(), end_bound.into(), message.into()));
+        self
+    }
+
+    /// Sends an error frame, which the code under test reads as a `Remote` error
+    pub fn send_error<V: Into<String>>(mut self, code: u64, message: V) -> ScriptedPeer {
+        self.steps.push_back(Step::SendError(code, message.into()));
+        self
+    }
+
+    /// Returns whether every step of the script has been played
+    pub fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Sends every message at the front of the script, up to the next expectation
+    fn send_ready(&mut self) {
+        loop {
+            let result = match self.steps.front() {
+                Some(Step::Send(message)) => self.peer.write(message),
+                Some(Step::SendBetween(beg, end, message)) => self.peer.write_between(beg, end, message),
+                Some(Step::SendError(code, message)) => self.peer.send_error(*code, message),
+                _ => return,
+            };
+            if let Err(e) = result {
+                self.fail(&format!("could not be sent: {}", e));
+            }
+            self.next_step();
+        }
+    }
+
+    /// Checks every frame written so far against the script
+    fn check_written(&mut self) {
+        loop {
+            self.send_ready();
+            let (expected, result) = match self.steps.front() {
+                Some(Step::Expect(message)) => (message.clone(), self.peer.read_next_message()),
+                Some(Step::ExpectBetween(beg, end, message)) => {
+                    let result = self.peer.read_next_tagged_message(&[(beg.as_str(), end.as_str())]).map(|(_, payload)| payload);
+                    (message.clone(), result)
+                }
+                Some(&Step::ExpectClose(reason)) => {
+                    match self.peer.read_next_message() {
+                        Err(ref e) if e.is_read_timeout() => return,
+                        Err(ref e) if matches!(*e.kind(), ErrorKind::Closed { .. }) => {
+                            let written = self.peer.peer_closed().unwrap_or(None);
+                            if written != reason {
+                                self.fail(&format!("the stream was closed with reason {:?} instead", written));
+                            }
+                        }
+                        Ok(written) => self.fail(&format!("got the message \"{}\" instead", written.escape_ascii())),
+                        Err(e) => self.fail(&describe_error(&e)),
+                    }
+                    // nothing can be written after a close frame, so only the sends that follow it are left to play
+                    self.next_step();
+                    self.send_ready();
+                    return;
+                }
+                _ => {
+                    match self.peer.read_next_message() {
+                        Err(ref e) if e.is_read_timeout() => return,
+                        Ok(written) => self.fail(&format!("the message \"{}\" was written", written.escape_ascii())),
+                        Err(e) => self.fail(&describe_error(&e)),
+                    }
+                }
+            };
+            match result {
+                Ok(ref written) if *written == expected => self.next_step(),
+                Ok(written) => self.fail(&diff(&expected, &written)),
+                Err(ref e) if e.is_read_timeout() => return,
+                Err(e) => self.fail(&describe_error(&e)),
+            }
+        }
+    }
+
+    fn next_step(&mut self) {
+        self.steps.pop_front();
+        self.steps_done += 1;
+    }
+
+    fn fail(&self, reason: &str) -> ! {
+        match self.steps.front() {
+            Some(step) => panic!("step {} of the script ({}) failed: {}", self.steps_done + 1, step.describe(), reason),
+            None => panic!("the script had already finished: {}", reason),
+        }
+    }
+}
+
+impl Read for ScriptedPeer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.send_ready();
+        if self.stream.available() == 0 {
+            if !self.is_finished() {
+                self.fail("the code under test read before writing what was expected");
+            }
+            return Ok(0);
+        }
+        self.stream.read(buf)
+    }
+}
+
+impl Write for ScriptedPeer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.stream.write(buf)?;
+        self.check_written();
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ScriptedPeer {
+    fn drop(&mut self) {
+        if !self.is_finished() && !thread::panicking() {
+            self.fail("the stream was dropped before the script finished");
+        }
+    }
+}
+
+fn describe_error(error: &Error) -> String {
+    match *error.kind() {
+        ErrorKind::UnknownBoundary(ref boundary) => format!("a frame beginning with `{}` was written instead", boundary),
+        ErrorKind::Closed { reason } => format!("the stream was closed with reason {:?}", reason),
+        _ => format!("the frame written could not be read: {}", error),
+    }
+}
+
+/// Shows the expected and written messages one above the other, pointing at the first byte that differs
+fn diff(expected: &[u8], written: &[u8]) -> String {
+    let common = expected.iter().zip(written).take_while(|&(a, b)| a == b).count();
+    let offset = expected[..common].escape_ascii().to_string().len();
+    let mut report = String::from("the message written does not match\n");
+    let _ = writeln!(report, "expected: \"{}\"", expected.escape_ascii());
+    let _ = writeln!(report, " written: \"{}\"", written.escape_ascii());
+    let _ = write!(report, "{}^ first difference at byte {}", " ".repeat(11 + offset), common);
+    report
+}
@@ -1,3 +1,6 @@
+// these helpers predate the lints, and their signatures are public
+#![allow(clippy::ptr_arg, clippy::needless_range_loop, clippy::collapsible_else_if)]
+
 /// Determines if a given Vector contains a given slice
 ///
 /// # Arguments
@@ -0,0 +1,75 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::{Cursor, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn channel_round_trip_test() {
+    let (left, right) = UnixStream::pair().unwrap();
+    let writer = stream::MessageWriter::new("--", "bound", "endbound", left, false);
+    let reader = stream::MessageReader::new("--", "bound", "endbound", right, false);
+
+    let (outgoing, to_writer) = mpsc::channel();
+    let (from_reader, incoming) = mpsc::channel();
+    let writing = writer.spawn_from(to_writer);
+    let reading = reader.spawn_into(from_reader);
+
+    for message in &["one", "two", "three"] {
+        outgoing.send(Vec::from(*message)).unwrap();
+    }
+    let received: Vec<_> = incoming.iter().take(3).collect();
+    assert_eq!(received, vec![Vec::from("one"), Vec::from("two"), Vec::from("three")]);
+
+    // hanging up the sender stops the writing thread, and closing the writer stops the reading thread
+    drop(outgoing);
+    let mut writer = writing.join().unwrap().unwrap();
+    writer.close(None).unwrap();
+    assert!(reading.join().unwrap().is_ok());
+    assert!(incoming.recv().is_err());
+}
+
+#[test]
+fn reader_stops_when_receiver_hangs_up_test() {
+    let mut writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    for message in &["first", "second"] {
+        assert!(writer.write(message.as_bytes()).unwrap() > message.len());
+    }
+    let reader = stream::MessageReader::new("--", "bound", "endbound", Cursor::new(writer.get_writer().clone()), false);
+
+    let (sender, receiver) = mpsc::channel();
+    drop(receiver);
+    let reader = reader.spawn_into(sender).join().unwrap().unwrap();
+    // only the first message was taken before the hang up was noticed
+    assert_eq!(reader.get_reader().position() as usize, writer.get_writer().len() / 2);
+}
+
+#[test]
+fn reader_reports_errors_test() {
+    let reader = stream::MessageReader::new("--", "bound", "endbound", Cursor::new(Vec::from("--bound5--abc")), false);
+    let (sender, receiver) = mpsc::channel();
+    assert!(reader.spawn_into(sender).join().unwrap().is_err());
+    assert!(receiver.recv().is_err());
+}
+
+#[test]
+fn stopped_reader_returns_at_read_timeout_test() {
+    let (left, right) = UnixStream::pair().unwrap();
+    right.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let reader = stream::MessageReader::new("--", "bound", "endbound", right, false);
+    let (sender, receiver) = mpsc::channel();
+    let reading = reader.spawn_into(sender);
+
+    // timeouts alone do not end the thread
+    thread::sleep(Duration::from_millis(50));
+    assert!(!reading.is_finished());
+    reading.stop();
+    assert!(reading.join().unwrap().is_ok());
+    assert!(receiver.recv().is_err());
+    drop(left);
+}
@@ -0,0 +1,70 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+
+fn messengers() -> (stream::DualMessenger<UnixStream>, stream::DualMessenger<UnixStream>) {
+    let (left, right) = UnixStream::pair().unwrap();
+    (
+        stream::DualMessenger::new("--", "bound", "endbound", left, false),
+        stream::DualMessenger::new("--", "bound", "endbound", right, false),
+    )
+}
+
+fn send(messenger: &mut stream::DualMessenger<UnixStream>, message: &[u8]) {
+    assert!(messenger.write(message).unwrap() > message.len());
+}
+
+#[test]
+fn close_handshake_test() {
+    let (mut client, mut server) = messengers();
+    send(&mut client, b"goodbye");
+    client.close(Some(42)).unwrap();
+    assert!(client.is_closed());
+
+    assert_eq!(server.read_next_message(), Ok(Vec::from("goodbye")));
+    match *server.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::Closed { reason } => assert_eq!(reason, Some(42)),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(server.peer_closed(), Some(Some(42)));
+    // later reads keep reporting the close rather than blocking
+    let error = server.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::Closed { reason: Some(42) }), "{:?}", error);
+
+    server.close(None).unwrap();
+    match *client.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::Closed { reason } => assert_eq!(reason, None),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn half_close_keeps_draining_test() {
+    let (mut client, mut server) = messengers();
+    client.close(None).unwrap();
+    assert!(client.write(b"too late").is_err());
+    // closing twice does not send a second close frame
+    client.close(Some(1)).unwrap();
+
+    send(&mut server, b"still arriving");
+    send(&mut server, b"and this");
+    assert_eq!(client.read_next_message(), Ok(Vec::from("still arriving")));
+    assert_eq!(client.read_next_message(), Ok(Vec::from("and this")));
+
+    let error = server.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::Closed { reason: None }), "{:?}", error);
+    assert_eq!(server.peer_closed(), Some(None));
+}
+
+#[test]
+fn crash_is_not_a_close_test() {
+    let (client, mut server) = messengers();
+    drop(client);
+
+    assert_eq!(server.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+    assert_eq!(server.peer_closed(), None);
+}
@@ -0,0 +1,108 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::net::UdpSocket;
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", true)
+}
+
+fn udp_pair() -> (UdpSocket, UdpSocket) {
+    let left = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let right = UdpSocket::bind("127.0.0.1:0").unwrap();
+    left.connect(right.local_addr().unwrap()).unwrap();
+    right.connect(left.local_addr().unwrap()).unwrap();
+    (left, right)
+}
+
+#[test]
+fn one_frame_per_datagram_test() {
+    let (left, right) = udp_pair();
+    let mut sender = stream::DatagramMessenger::new(config(), left);
+    let mut receiver = stream::DatagramMessenger::new(config(), right);
+
+    for message in &["first", "second"] {
+        assert!(sender.send(message.as_bytes()).unwrap() > message.len());
+    }
+    assert_eq!(receiver.receive(), Ok(Vec::from("first")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("second")));
+}
+
+#[test]
+fn malformed_datagrams_are_isolated_test() {
+    let (left, right) = udp_pair();
+    let mut receiver = stream::DatagramMessenger::new(config(), right);
+    let mut sender = stream::DatagramMessenger::new(config(), left.try_clone().unwrap());
+
+    // a frame claiming more bytes than the datagram holds
+    left.send(b"--bound50--short--endbound--").unwrap();
+    assert_eq!(receiver.receive(), Err(stream::Error::from(stream::ErrorKind::MalformedDatagram)));
+    left.send(b"--bound18446744073709551615--x--endbound--").unwrap();
+    assert_eq!(receiver.receive(), Err(stream::Error::from(stream::ErrorKind::MalformedDatagram)));
+
+    // a whole frame with something after it
+    let mut valid = stream::MessageWriter::new_from_config(config(), Vec::new());
+    assert!(std::io::Write::write(&mut valid, b"payload").unwrap() > 7);
+    let mut trailing = valid.get_writer().clone();
+    trailing.extend_from_slice(b"junk");
+    left.send(&trailing).unwrap();
+    assert_eq!(receiver.receive(), Err(stream::Error::from(stream::ErrorKind::MalformedDatagram)));
+
+    // a frame whose message no longer matches its hash
+    let mut corrupted = valid.get_writer().clone();
+    let last_payload_byte = corrupted.len() - "--endbound--".len() - 1;
+    corrupted[last_payload_byte] ^= 1;
+    left.send(&corrupted).unwrap();
+    assert_eq!(receiver.receive(), Err(stream::Error::from(stream::ErrorKind::HashMismatch)));
+
+    left.send(valid.get_writer()).unwrap();
+    assert_eq!(receiver.receive(), Ok(Vec::from("payload")));
+
+    left.send(b"nonsense").unwrap();
+    assert!(sender.send(b"valid").unwrap() > 5);
+    assert_eq!(receiver.receive_valid(), Ok(Vec::from("valid")));
+}
+
+#[test]
+fn oversized_message_is_refused_test() {
+    let (left, _right) = udp_pair();
+    let mut sender = stream::DatagramMessenger::new(config(), left);
+    sender.set_max_datagram_size(128);
+    let error = sender.send(&[0; 128]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn datagram_of_maximum_size_test() {
+    let (left, right) = udp_pair();
+    let mut sender = stream::DatagramMessenger::new(config(), left.try_clone().unwrap());
+    let mut receiver = stream::DatagramMessenger::new(config(), right);
+    let size = sender.send(b"exactly").unwrap();
+    sender.set_max_datagram_size(size);
+    receiver.set_max_datagram_size(size);
+
+    assert_eq!(sender.send(b"exactly").unwrap(), size);
+    assert_eq!(receiver.receive(), Ok(Vec::from("exactly")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("exactly")));
+
+    assert_eq!(sender.send(b"exactly!").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    left.send(&vec![b'-'; size + 1]).unwrap();
+    assert_eq!(receiver.receive(), Err(stream::Error::from(stream::ErrorKind::MalformedDatagram)));
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_datagram_sequence_test() {
+    use std::os::unix::net::UnixDatagram;
+
+    let (left, right) = UnixDatagram::pair().unwrap();
+    let config = config().with_sequence_numbers(true);
+    let mut sender = stream::DatagramMessenger::new(config.clone(), left);
+    let mut receiver = stream::DatagramMessenger::new(config, right);
+
+    assert!(sender.send(b"zero").unwrap() > 4);
+    assert!(sender.send(b"one").unwrap() > 3);
+    assert_eq!(receiver.receive(), Ok(Vec::from("zero")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("one")));
+}
@@ -0,0 +1,81 @@
+extern crate messenger_plus;
+#[macro_use]
+extern crate messenger_plus_derive;
+
+use messenger_plus::stream;
+use messenger_plus::stream::Message;
+use messenger_plus::stream::memory;
+
+use std::io::Write;
+
+#[derive(Debug, PartialEq, Message)]
+enum Command {
+    #[message(boundary = "ping")]
+    Ping,
+    #[message(boundary = "data", ending = "stopdata")]
+    Data(Vec<u8>),
+    #[message(boundary = "text")]
+    Text { body: Vec<u8> },
+}
+
+#[derive(Debug, PartialEq, Message)]
+enum OnlyPing {
+    #[message(boundary = "ping")]
+    Ping,
+}
+
+#[test]
+fn derived_boundaries_test() {
+    assert_eq!(Command::boundaries(), &[("ping", "endping"), ("data", "stopdata"), ("text", "endtext")]);
+    assert_eq!(Command::Ping.boundary(), ("ping", "endping"));
+    assert_eq!(Command::Data(Vec::from("hi")).payload(), b"hi");
+}
+
+#[test]
+fn writes_variant_boundaries_test() {
+    let mut message_writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+
+    message_writer.send_message(&Command::Ping).unwrap();
+    message_writer.send_message(&Command::Data(Vec::from("hello"))).unwrap();
+
+    assert_eq!(*message_writer.get_writer(), Vec::from("--ping0----endping----data5--hello--stopdata--"));
+}
+
+#[test]
+fn typed_round_trip_test() {
+    let mut messenger = stream::DualMessenger::new("--", "bound", "endbound", memory::loopback(), false);
+    let messages = vec![
+        Command::Data(Vec::from("hello, world!")),
+        Command::Ping,
+        Command::Text { body: Vec::from("--text3--") },
+    ];
+
+    for message in &messages {
+        messenger.send_message(message).unwrap();
+    }
+    for message in messages {
+        assert_eq!(messenger.read_next_typed_message::<Command>(), Ok(message));
+    }
+    assert_eq!(messenger.read_next_typed_message::<Command>(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+}
+
+#[test]
+fn unknown_boundary_test() {
+    let mut messenger = stream::DualMessenger::new("--", "bound", "endbound", memory::loopback(), false);
+
+    messenger.send_message(&Command::Data(Vec::from("skipped"))).unwrap();
+    messenger.send_message(&Command::Ping).unwrap();
+
+    let error = messenger.read_next_typed_message::<OnlyPing>().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownBoundary(ref boundary) if boundary == "data"), "{:?}", error);
+    assert_eq!(messenger.read_next_typed_message::<OnlyPing>(), Ok(OnlyPing::Ping));
+}
+
+#[test]
+fn untyped_frames_are_readable_test() {
+    let mut messenger = stream::DualMessenger::new("--", "data", "stopdata", memory::loopback(), false);
+
+    assert_eq!(messenger.write(b"raw").unwrap(), Vec::from("--data3--raw--stopdata--").len());
+
+    assert_eq!(messenger.read_next_typed_message::<Command>(), Ok(Command::Data(Vec::from("raw"))));
+}
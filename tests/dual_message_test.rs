@@ -1,53 +1,12 @@
 extern crate messenger_plus;
 use messenger_plus::stream;
+use messenger_plus::stream::memory;
 
-use std::io::{Read, Write, Result};
-
-#[derive(Debug)]
-struct RandomReadWrite {
-    info: Vec<u8>,
-}
-
-impl RandomReadWrite {
-    fn new() -> RandomReadWrite {
-        RandomReadWrite {
-            info: Vec::new(),
-        }
-    }
-}
-
-impl Write for RandomReadWrite {
-
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let mut count = 0;
-        for i in buf {
-            self.info.push(*i);
-            count = count + 1;
-        }
-        Ok(count)
-    }
-
-    fn flush(&mut self) -> Result<()> {
-        Ok(())
-    }
-
-}
-
-impl Read for RandomReadWrite {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        for i in 0..buf.len() {
-            if self.info.is_empty() {
-                return Ok(i as usize);
-            }
-            buf[i] = self.info.remove(0);
-        }
-        Ok(buf.len())
-    }
-}
+use std::io::Write;
 
 #[test]
 fn dual_messenger_test() {
-    let mut random_reader = RandomReadWrite::new();
+    let mut random_reader = memory::loopback();
     let mut message_reader = messenger_plus::stream::DualMessenger::new(String::from("--"), String::from("bound"), String::from("endbound"), &mut random_reader, false);
     let buf: &[u8] = "hello, world!".as_ref();
 
@@ -59,7 +18,7 @@ fn dual_messenger_test() {
 
 #[test]
 fn dual_message_multi_test() {
-    let mut random_reader = RandomReadWrite::new();
+    let mut random_reader = memory::loopback();
     let mut message_reader = messenger_plus::stream::DualMessenger::new(String::from("--"), String::from("bound"), String::from("endbound"), &mut random_reader, false);
     let buf: &[u8] = "hello, world!".as_ref();
 
@@ -74,7 +33,7 @@ fn dual_message_multi_test() {
 
 #[test]
 fn dual_message_large_multi_test() {
-    let mut random_reader = RandomReadWrite::new();
+    let mut random_reader = memory::loopback();
     let mut message_reader = messenger_plus::stream::DualMessenger::new(String::from("--"), String::from("bound"), String::from("endbound"), &mut random_reader, false);
     let buf: &[u8] = "hello, world!".as_ref();
 
@@ -86,4 +45,4 @@ fn dual_message_large_multi_test() {
         assert_eq!(message_reader.read_next_message(), Ok(Vec::from("hello, world!")));
     }
     assert_eq!(message_reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
-}
\ No newline at end of file
+}
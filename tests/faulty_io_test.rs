@@ -0,0 +1,117 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+use messenger_plus::testing::{check_conformance, FaultSchedule, FaultyIo};
+
+use std::io::{self, Cursor, Read, Write};
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false)
+}
+
+fn read_all(schedule: FaultSchedule) -> (Vec<u8>, usize) {
+    let mut faulty = FaultyIo::new(Cursor::new((0..=255).collect::<Vec<u8>>()), schedule);
+    let mut received = Vec::new();
+    let mut buf = [0; 64];
+    loop {
+        match faulty.read(&mut buf) {
+            Ok(0) => return (received, faulty.faults_injected()),
+            Ok(count) => received.extend_from_slice(&buf[..count]),
+            Err(e) => assert!(e.kind() == io::ErrorKind::Interrupted || e.kind() == io::ErrorKind::WouldBlock),
+        }
+    }
+}
+
+#[test]
+fn schedule_is_reproducible_test() {
+    let schedule = FaultSchedule::new(7).with_short_reads(0.5).with_interrupts(0.2).with_would_blocks(0.2).with_bit_flips(0.1);
+    let (first, first_faults) = read_all(schedule);
+    assert_eq!(read_all(schedule), (first.clone(), first_faults));
+    assert!(first_faults > 0);
+    assert_eq!(first.len(), 256);
+    assert_ne!(first, (0..=255).collect::<Vec<u8>>());
+}
+
+#[test]
+fn short_reads_and_truncation_test() {
+    let schedule = FaultSchedule::new(1).with_short_reads(1.0).with_truncation(10);
+    let mut faulty = FaultyIo::new(Cursor::new(vec![1; 100]), schedule);
+    let mut buf = [0; 8];
+    assert_eq!(faulty.read(&mut buf).unwrap(), 1);
+
+    let mut rest = Vec::new();
+    faulty.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest.len(), 9);
+}
+
+#[test]
+fn interrupted_first_byte_is_retried_test() {
+    let mut writer = stream::MessageWriter::new_from_config(config(), Vec::new());
+    assert!(writer.write(b"hello").unwrap() > 5);
+    let frame = writer.get_writer().clone();
+
+    // the very first read is interrupted, before any byte of the frame
+    let schedule = (0..).map(|seed| FaultSchedule::new(seed).with_interrupts(0.5))
+        .find(|&schedule| FaultyIo::new(io::empty(), schedule).read(&mut [0]).is_err())
+        .unwrap();
+    let mut reader = stream::MessageReader::new_from_config(config(), FaultyIo::new(Cursor::new(frame), schedule));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("hello")));
+}
+
+#[test]
+fn write_that_would_block_keeps_rest_of_frame_test() {
+    let schedule = FaultSchedule::new(3).with_partial_writes(1.0).with_would_blocks(0.5);
+    let mut writer = stream::MessageWriter::new_from_config(config().with_sequence_numbers(true), FaultyIo::new(Vec::new(), schedule));
+    for message in &[&b"first"[..], b"second", b"third"] {
+        loop {
+            match writer.write(message) {
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock && writer.pending_bytes() == 0 => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    while writer.flush().is_err() {}
+                    break;
+                }
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+    while writer.flush().is_err() {}
+
+    let stream = writer.get_writer().get_ref().clone();
+    let mut reader = stream::MessageReader::new_from_config(config().with_sequence_numbers(true), Cursor::new(stream));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("first")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("second")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("third")));
+    assert_eq!(reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+}
+
+#[test]
+fn bit_flips_are_caught_by_hashing_test() {
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", true);
+    let mut writer = stream::MessageWriter::new_from_config(config.clone(), Vec::new());
+    assert!(writer.write(b"hello").unwrap() > 5);
+    let frame = writer.get_writer().clone();
+
+    let mut faulty = FaultyIo::new(Cursor::new(frame), FaultSchedule::new(0).with_bit_flips(1.0));
+    let mut corrupted = Vec::new();
+    faulty.read_to_end(&mut corrupted).unwrap();
+    let mut reader = stream::MessageReader::new_from_config(config, Cursor::new(corrupted));
+    assert!(reader.read_next_message().is_err());
+}
+
+#[test]
+fn conformance_test() {
+    let configs = [
+        config(),
+        config().with_sequence_numbers(true),
+        stream::StreamConfiguration::new("--", "bound", "endbound", true),
+        stream::StreamConfiguration::new("|", "begin", "end", true).with_sequence_numbers(true),
+    ];
+    for config in &configs {
+        for seed in 0..4 {
+            if let Err(failure) = check_conformance(config, seed) {
+                panic!("{:?}: {}", config, failure);
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::{Cursor, Write};
+
+#[test]
+fn hashed_message_test() {
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", true).with_sequence_numbers(true);
+    let mut writer = stream::MessageWriter::new_from_config(config.clone(), Vec::new());
+    assert!(writer.write(b"intact").unwrap() > 6);
+    assert!(writer.write(b"damaged").unwrap() > 7);
+    assert!(writer.write(b"after").unwrap() > 5);
+
+    let mut bytes = writer.get_writer().clone();
+    let damaged = bytes.windows(7).position(|window| window == b"damaged").unwrap();
+    bytes[damaged] = b'D';
+
+    let mut reader = stream::MessageReader::new_from_config(config, Cursor::new(bytes));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("intact")));
+    assert_eq!(reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::HashMismatch)));
+    // the corrupted message never counted, so the next one reveals the gap it left
+    let error = reader.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::SequenceGap { expected: 1, received: 2 }), "{:?}", error);
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("after")));
+}
+
+#[test]
+fn frames_between_other_boundaries_are_not_hashed_test() {
+    let mut hashed = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), true);
+    let mut plain = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    for writer in [&mut hashed, &mut plain] {
+        assert!(writer.write_between("note", "endnote", b"aside").unwrap() > 5);
+        assert!(writer.send_error(404, "not found").unwrap() > 9);
+    }
+    assert_eq!(hashed.get_writer(), plain.get_writer());
+}
@@ -0,0 +1,64 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+fn heartbeat_messenger(socket: UnixStream, heartbeat: stream::Heartbeat) -> stream::DualMessenger<UnixStream> {
+    socket.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
+    let mut messenger = stream::DualMessenger::new("--", "bound", "endbound", socket, false);
+    messenger.set_heartbeat(Some(heartbeat));
+    messenger
+}
+
+fn send(messenger: &mut stream::DualMessenger<UnixStream>, message: &[u8]) {
+    assert!(messenger.write(message).unwrap() > message.len());
+    messenger.flush().unwrap();
+}
+
+#[test]
+fn heartbeats_are_not_messages_test() {
+    let heartbeat = stream::Heartbeat::new(Duration::from_millis(10), Duration::from_secs(5));
+    let (left, right) = UnixStream::pair().unwrap();
+    let echo = thread::spawn(move || {
+        let mut messenger = heartbeat_messenger(right, heartbeat);
+        for _ in 0..2 {
+            let message = messenger.read_next_message().unwrap();
+            send(&mut messenger, &message);
+        }
+        // keep answering pings until the other side is done
+        while messenger.read_next_message().is_ok() {}
+    });
+
+    let mut messenger = heartbeat_messenger(left, heartbeat);
+    assert_eq!(messenger.round_trip_time(), None);
+    send(&mut messenger, b"hello");
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("hello")));
+    thread::sleep(Duration::from_millis(30));
+    send(&mut messenger, b"again");
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("again")));
+    assert!(messenger.round_trip_time().is_some());
+
+    drop(messenger);
+    echo.join().unwrap();
+}
+
+#[test]
+fn silent_peer_times_out_test() {
+    let heartbeat = stream::Heartbeat::new(Duration::from_millis(10), Duration::from_millis(50));
+    let (left, _silent) = UnixStream::pair().unwrap();
+    let mut messenger = heartbeat_messenger(left, heartbeat);
+
+    assert_eq!(messenger.read_next_message(), Err(stream::Error::from(stream::ErrorKind::PeerTimeout)));
+}
+
+#[test]
+fn heartbeat_settings_test() {
+    let heartbeat = stream::Heartbeat::new(Duration::from_secs(10), Duration::from_secs(1));
+    assert_eq!(heartbeat.interval(), Duration::from_secs(10));
+    assert_eq!(heartbeat.timeout(), Duration::from_secs(10));
+}
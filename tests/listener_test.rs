@@ -0,0 +1,103 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false)
+}
+
+fn send<T: std::io::Read + Write>(messenger: &mut stream::DualMessenger<T>, message: &[u8]) {
+    assert!(messenger.write(message).unwrap() > message.len());
+}
+
+fn echo<T: std::io::Read + Write>(mut messenger: stream::DualMessenger<T>) {
+    while let Ok(message) = messenger.read_next_message() {
+        send(&mut messenger, &message);
+    }
+}
+
+#[test]
+fn accept_tcp_test() {
+    let listener = stream::MessageListener::bind_tcp("127.0.0.1:0", config()).unwrap();
+    let address = listener.get_listener().local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let mut messenger = stream::DualMessenger::new_from_config(config(), TcpStream::connect(address).unwrap());
+        send(&mut messenger, b"hello");
+        messenger.read_next_message().unwrap()
+    });
+
+    let mut server = listener.incoming().next().unwrap().unwrap();
+    let message = server.read_next_message().unwrap();
+    send(&mut server, &message);
+    assert_eq!(client.join().unwrap(), Vec::from("hello"));
+}
+
+#[test]
+fn serve_limits_connections_test() {
+    let listener = Arc::new(stream::MessageListener::bind_tcp("127.0.0.1:0", config()).unwrap());
+    let address = listener.get_listener().local_addr().unwrap();
+    let shutdown = stream::Shutdown::new();
+    let server = {
+        let listener = listener.clone();
+        let shutdown = shutdown.clone();
+        thread::spawn(move || listener.serve(1, &shutdown, echo))
+    };
+
+    let connect = || {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        stream::DualMessenger::new_from_config(config(), stream)
+    };
+    let mut first = connect();
+    send(&mut first, b"first");
+    assert_eq!(first.read_next_message(), Ok(Vec::from("first")));
+
+    // the second connection is only picked up once the first one ends
+    let mut second = connect();
+    send(&mut second, b"second");
+    assert!(second.read_next_message().is_err());
+    drop(first);
+    let reply = (0..25).filter_map(|_| second.read_next_message().ok()).next();
+    assert_eq!(reply, Some(Vec::from("second")));
+
+    drop(second);
+    shutdown.shutdown();
+    server.join().unwrap().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_unix_shutdown_test() {
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::temp_dir().join(format!("messenger_plus_listener_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = stream::MessageListener::bind_unix(&path, config()).unwrap();
+    let shutdown = stream::Shutdown::new();
+    let server = {
+        let shutdown = shutdown.clone();
+        thread::spawn(move || listener.serve(4, &shutdown, echo))
+    };
+
+    let mut clients: Vec<_> = (0..3).map(|_| stream::DualMessenger::new_from_config(config(), UnixStream::connect(&path).unwrap())).collect();
+    for (index, client) in clients.iter_mut().enumerate() {
+        send(client, format!("client {}", index).as_bytes());
+    }
+    for (index, client) in clients.iter_mut().enumerate() {
+        assert_eq!(client.read_next_message(), Ok(Vec::from(format!("client {}", index))));
+    }
+
+    // serve waits for the open connections before returning
+    shutdown.shutdown();
+    thread::sleep(Duration::from_millis(50));
+    assert!(!server.is_finished());
+    drop(clients);
+    server.join().unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
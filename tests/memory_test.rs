@@ -0,0 +1,100 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+use messenger_plus::stream::memory;
+use messenger_plus::stream::{ReadTimeout, TryCloneStream};
+
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false).with_sequence_numbers(true)
+}
+
+fn send<W: Write>(writer: &mut W, message: &[u8]) {
+    assert!(writer.write(message).unwrap() > message.len());
+}
+
+#[test]
+fn messengers_across_threads_test() {
+    let (left, right) = memory::duplex();
+    let echo = thread::spawn(move || {
+        let mut messenger = stream::DualMessenger::new_from_config(config(), right);
+        loop {
+            match messenger.read_next_message() {
+                Ok(message) => send(&mut messenger, &message),
+                Err(e) => return e,
+            }
+        }
+    });
+
+    let mut messenger = stream::DualMessenger::new_from_config(config(), left);
+    for message in &[&b"first"[..], b"second", b"third"] {
+        send(&mut messenger, message);
+        assert_eq!(messenger.read_next_message(), Ok(Vec::from(*message)));
+    }
+    drop(messenger);
+    assert_eq!(echo.join().unwrap(), stream::Error::from(stream::ErrorKind::BufferEmpty));
+}
+
+#[test]
+fn nonblocking_read_test() {
+    let (mut left, mut right) = memory::duplex();
+    right.set_nonblocking(true);
+    let mut buf = [0; 8];
+    assert_eq!(right.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    left.write_all(b"ready").unwrap();
+    assert_eq!(right.available(), 5);
+    assert_eq!(right.read(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"ready");
+}
+
+#[test]
+fn read_timeout_test() {
+    let (_left, mut right) = memory::duplex();
+    right.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(right.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+}
+
+#[test]
+fn dropped_peer_test() {
+    let (mut left, mut right) = memory::duplex();
+    left.write_all(b"bye").unwrap();
+    drop(left);
+
+    let mut received = Vec::new();
+    right.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"bye");
+    assert_eq!(right.write(b"anyone?").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn cloned_end_stays_open_test() {
+    let (left, mut right) = memory::duplex();
+    let mut clone = left.try_clone_stream().unwrap();
+    drop(left);
+    clone.write_all(b"still here").unwrap();
+    drop(clone);
+
+    let mut received = Vec::new();
+    right.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"still here");
+}
+
+#[test]
+fn capacity_holds_up_writer_test() {
+    let (mut left, mut right) = memory::duplex_with_capacity(4);
+    left.set_nonblocking(true);
+    assert_eq!(left.write(b"abcdef").unwrap(), 4);
+    assert_eq!(left.write(b"ef").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    left.set_nonblocking(false);
+    let writer = thread::spawn(move || left.write_all(b"efghijkl"));
+    let mut received = Vec::new();
+    right.read_to_end(&mut received).unwrap();
+    writer.join().unwrap().unwrap();
+    assert_eq!(received, b"abcdefghijkl");
+}
@@ -0,0 +1,96 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::cell::RefCell;
+use std::io::Write;
+
+fn interleaved_frames() -> Vec<u8> {
+    let mut message_writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    assert_eq!(message_writer.write(b"first").unwrap(), Vec::from("--bound5--first--endbound--").len());
+    message_writer.write_between("log", "endlog", b"second").unwrap();
+    message_writer.write_between("bound_b", "endbound_b", b"third").unwrap();
+    message_writer.write_between("unknown", "endunknown", b"fourth").unwrap();
+    message_writer.write_between("log", "endlog", b"fifth").unwrap();
+    message_writer.get_writer().clone()
+}
+
+fn tagged(beginning_boundary: &str, ending_boundary: &str, message: &str) -> stream::TaggedMessage {
+    stream::TaggedMessage {
+        beginning_boundary: String::from(beginning_boundary),
+        ending_boundary: String::from(ending_boundary),
+        message: Vec::from(message),
+    }
+}
+
+#[test]
+fn reads_interleaved_boundaries_test() {
+    let frames = interleaved_frames();
+    let boundaries = vec![("bound", "endbound"), ("log", "endlog"), ("bound_b", "endbound_b")];
+    let mut message_reader = stream::MultiMessageReader::new("--", boundaries, frames.as_slice());
+
+    assert_eq!(message_reader.read_next_message(), Ok(tagged("bound", "endbound", "first")));
+    assert_eq!(message_reader.read_next_message(), Ok(tagged("log", "endlog", "second")));
+    assert_eq!(message_reader.read_next_message(), Ok(tagged("bound_b", "endbound_b", "third")));
+    let error = message_reader.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownBoundary(ref boundary) if boundary == "unknown"), "{:?}", error);
+    assert_eq!(message_reader.read_next_message(), Ok(tagged("log", "endlog", "fifth")));
+    assert_eq!(message_reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+}
+
+#[test]
+fn mismatched_ending_test() {
+    let mut message_writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    message_writer.write_between("bound", "endlog", b"first").unwrap();
+    message_writer.write_between("bound", "endbound", b"second").unwrap();
+    let frames = message_writer.get_writer().clone();
+    let mut message_reader = stream::MultiMessageReader::new("--", vec![("bound", "endbound")], frames.as_slice());
+
+    assert_eq!(message_reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::EndingDoesntMatch)));
+    assert_eq!(message_reader.read_next_message(), Ok(tagged("bound", "endbound", "second")));
+}
+
+#[test]
+fn dispatcher_routes_by_boundary_test() {
+    let frames = interleaved_frames();
+    let bound_messages = RefCell::new(Vec::new());
+    let log_messages = RefCell::new(Vec::new());
+
+    let mut dispatcher = stream::Dispatcher::new("--", frames.as_slice());
+    dispatcher
+        .register("bound", "endbound", |message| bound_messages.borrow_mut().push(message))
+        .register("bound_b", "endbound_b", |message| bound_messages.borrow_mut().push(message))
+        .register("log", "endlog", |message| log_messages.borrow_mut().push(message));
+
+    let error = dispatcher.run().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownBoundary(ref boundary) if boundary == "unknown"), "{:?}", error);
+    assert_eq!(dispatcher.run(), Ok(()));
+    drop(dispatcher);
+
+    assert_eq!(bound_messages.into_inner(), vec![Vec::from("first"), Vec::from("third")]);
+    assert_eq!(log_messages.into_inner(), vec![Vec::from("second"), Vec::from("fifth")]);
+}
+
+#[test]
+fn dispatcher_reregister_test() {
+    let frames = interleaved_frames();
+    let mut first_count = 0;
+    let mut second_count = 0;
+    {
+        let mut dispatcher = stream::Dispatcher::new("--", frames.as_slice());
+        dispatcher.register("bound", "endbound", |_| first_count += 1);
+        dispatcher.register("bound", "endbound", |_| second_count += 1);
+
+        assert_eq!(dispatcher.dispatch_next(), Ok(String::from("bound")));
+    }
+
+    assert_eq!(first_count, 0);
+    assert_eq!(second_count, 1);
+}
+
+#[test]
+#[should_panic(expected = "cannot be told apart")]
+fn boundaries_differing_by_digits_are_refused_test() {
+    let mut message_reader = stream::MultiMessageReader::new("--", vec![("msg", "endmsg")], &b""[..]);
+    message_reader.add_boundaries("msg1", "endmsg1");
+}
@@ -0,0 +1,260 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+use messenger_plus::stream::memory;
+
+use std::io;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+/// Two ends of an in-memory stream whose reads return `WouldBlock` once nothing is left, rather than waiting
+fn pipe() -> (memory::MemoryStream, memory::MemoryStream) {
+    let (left, right) = memory::duplex();
+    left.set_nonblocking(true);
+    right.set_nonblocking(true);
+    (left, right)
+}
+
+fn send<W: Write>(writer: &mut W, message: &[u8]) {
+    assert!(writer.write(message).unwrap() > message.len());
+}
+
+fn multiplexers() -> (stream::Multiplexer<memory::MemoryStream>, stream::Multiplexer<memory::MemoryStream>) {
+    let (left, right) = pipe();
+    (
+        stream::Multiplexer::new("--", "bound", "endbound", left, false),
+        stream::Multiplexer::new("--", "bound", "endbound", right, false),
+    )
+}
+
+fn flow_controlled_multiplexers(flow_control: stream::FlowControl) -> (stream::Multiplexer<memory::MemoryStream>, stream::Multiplexer<memory::MemoryStream>) {
+    let (left, right) = pipe();
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    (
+        stream::Multiplexer::new_with_flow_control(config.clone(), flow_control, left),
+        stream::Multiplexer::new_with_flow_control(config, flow_control, right),
+    )
+}
+
+#[test]
+fn channels_are_independent_test() {
+    let (client, server) = multiplexers();
+    let mut first = client.open_channel(1).unwrap();
+    let mut second = client.open_channel(2).unwrap();
+
+    send(&mut second, b"for two");
+    send(&mut first, b"for one");
+    send(&mut second, b"for two again");
+
+    let mut accepted_first = server.accept_channel().unwrap();
+    let mut accepted_second = server.accept_channel().unwrap();
+    assert_eq!(accepted_first.id(), 1);
+    assert_eq!(accepted_second.id(), 2);
+
+    assert_eq!(accepted_first.read_next_message(), Ok(Vec::from("for one")));
+    assert_eq!(accepted_second.read_next_message(), Ok(Vec::from("for two")));
+    assert_eq!(accepted_second.read_next_message(), Ok(Vec::from("for two again")));
+    assert_eq!(io::Error::from(accepted_second.read_next_message().unwrap_err()).kind(), io::ErrorKind::WouldBlock);
+
+    send(&mut accepted_first, b"reply");
+    assert_eq!(first.read_next_message(), Ok(Vec::from("reply")));
+}
+
+#[test]
+fn close_drains_queue_test() {
+    let (client, server) = multiplexers();
+    let mut channel = client.open_channel(7).unwrap();
+    send(&mut channel, b"last words");
+    channel.close().unwrap();
+
+    let mut accepted = server.accept_channel().unwrap();
+    assert_eq!(accepted.read_next_message(), Ok(Vec::from("last words")));
+    let error = accepted.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::ChannelClosed(7)), "{:?}", error);
+    assert!(accepted.write(b"too late").is_err());
+}
+
+#[test]
+fn dropping_closes_channel_test() {
+    let (client, server) = multiplexers();
+    drop(client.open_channel(3).unwrap());
+
+    let mut accepted = server.accept_channel().unwrap();
+    let error = accepted.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::ChannelClosed(3)), "{:?}", error);
+}
+
+#[test]
+fn reopening_channel_test() {
+    let (client, server) = multiplexers();
+    let channel = client.open_channel(1).unwrap();
+
+    let error = client.open_channel(1).err().unwrap();
+    assert!(matches!(*error.kind(), stream::ErrorKind::ChannelAlreadyOpen(1)), "{:?}", error);
+    channel.close().unwrap();
+    assert!(client.open_channel(1).is_ok());
+
+    let accepted = server.accept_channel().unwrap();
+    assert_eq!(accepted.id(), 1);
+    let error = server.open_channel(1).err().unwrap();
+    assert!(matches!(*error.kind(), stream::ErrorKind::ChannelAlreadyOpen(1)), "{:?}", error);
+}
+
+#[test]
+fn credit_is_exhausted_and_returned_test() {
+    let (client, server) = flow_controlled_multiplexers(stream::FlowControl::new(10, 100));
+    let mut channel = client.open_channel(1).unwrap();
+    let mut accepted = server.accept_channel().unwrap();
+    assert_eq!(channel.send_credit(), 10);
+
+    send(&mut channel, b"12345");
+    assert_eq!(channel.send_credit(), 5);
+    channel.set_nonblocking(true);
+    send(&mut channel, b"12345");
+    assert_eq!(channel.send_credit(), 0);
+    assert_eq!(channel.write(b"1").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    assert_eq!(accepted.read_next_message(), Ok(Vec::from("12345")));
+    channel.set_nonblocking(false);
+    send(&mut channel, b"1");
+    assert_eq!(channel.send_credit(), 4);
+}
+
+#[test]
+fn slow_channel_does_not_stall_others_test() {
+    let (client, server) = flow_controlled_multiplexers(stream::FlowControl::new(4, 100));
+    let mut slow = client.open_channel(1).unwrap();
+    let mut fast = client.open_channel(2).unwrap();
+    let _accepted_slow = server.accept_channel().unwrap();
+    let mut accepted_fast = server.accept_channel().unwrap();
+
+    send(&mut slow, b"full");
+    slow.set_nonblocking(true);
+    assert_eq!(slow.write(b"more").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    for _ in 0..10 {
+        send(&mut fast, b"fast");
+        assert_eq!(accepted_fast.read_next_message(), Ok(Vec::from("fast")));
+    }
+}
+
+#[test]
+fn oversized_message_test() {
+    let (client, server) = flow_controlled_multiplexers(stream::FlowControl::new(4, 100));
+    let mut channel = client.open_channel(1).unwrap();
+    let _accepted = server.accept_channel().unwrap();
+    send(&mut channel, b"ok");
+
+    assert_eq!(channel.write(b"too large").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn global_buffer_cap_test() {
+    let (left, right) = pipe();
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    let client = stream::Multiplexer::new_with_flow_control(config.clone(), stream::FlowControl::new(8, 100), left);
+    let server = stream::Multiplexer::new_with_flow_control(config, stream::FlowControl::new(8, 12), right);
+    let mut first = client.open_channel(1).unwrap();
+    let mut accepted_first = server.accept_channel().unwrap();
+    let _second = server.open_channel(2).unwrap();
+
+    send(&mut first, b"12345678");
+    assert_eq!(first.send_credit(), 0);
+
+    assert_eq!(accepted_first.read_next_message(), Ok(Vec::from("12345678")));
+    // the server's own channel still holds its initial window, so only 4 of the 12 bytes are left for the first
+    send(&mut first, b"y");
+    assert_eq!(first.send_credit(), 3);
+}
+
+#[test]
+fn opens_past_buffer_cap_are_refused_test() {
+    let (client, server) = flow_controlled_multiplexers(stream::FlowControl::new(8, 12));
+    let mut first = client.open_channel(1).unwrap();
+    let mut second = client.open_channel(2).unwrap();
+    send(&mut first, b"hello");
+
+    let mut accepted_first = server.accept_channel().unwrap();
+    assert_eq!(accepted_first.read_next_message(), Ok(Vec::from("hello")));
+    assert!(server.accept_channel().is_err());
+
+    let error = second.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::ChannelClosed(2)), "{:?}", error);
+}
+
+#[test]
+fn window_overflow_is_a_violation_test() {
+    let (left, right) = pipe();
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    let server = stream::Multiplexer::new_with_flow_control(config, stream::FlowControl::new(4, 100), right);
+    let mut raw = stream::MessageWriter::new("--", "bound", "endbound", left, false);
+    raw.write_between("bound!open", "endbound!open", b"1--").unwrap();
+    raw.write_between("bound!window", "endbound!window", b"1--18446744073709551615--4--").unwrap();
+    raw.write_between("bound", "endbound", b"1--more").unwrap();
+
+    let mut accepted = server.accept_channel().unwrap();
+    let error = accepted.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::FlowControlViolation(1)), "{:?}", error);
+}
+
+#[test]
+fn failed_write_gives_credit_back_test() {
+    // just enough room for the open frame, so the data frame after it cannot go out at all
+    let (left, right) = memory::duplex_with_capacity("--bound!open3--1----endbound!open--".len());
+    left.set_nonblocking(true);
+    right.set_nonblocking(true);
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    let client = stream::Multiplexer::new_with_flow_control(config.clone(), stream::FlowControl::new(8, 100), left);
+    let server = stream::Multiplexer::new_with_flow_control(config, stream::FlowControl::new(8, 100), right);
+    let mut channel = client.open_channel(1).unwrap();
+
+    assert_eq!(channel.write(b"hello").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(channel.send_credit(), 8);
+
+    let mut accepted = server.accept_channel().unwrap();
+    send(&mut channel, b"hello");
+    assert_eq!(channel.send_credit(), 3);
+    assert_eq!(accepted.read_next_message(), Ok(Vec::from("hello")));
+}
+
+#[test]
+fn flow_control_violation_test() {
+    let (left, right) = pipe();
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    let server = stream::Multiplexer::new_with_flow_control(config, stream::FlowControl::new(4, 100), right);
+    let mut raw = stream::MessageWriter::new("--", "bound", "endbound", left, false);
+    raw.write_between("bound!open", "endbound!open", b"1--").unwrap();
+    raw.write_between("bound", "endbound", b"1--more than four").unwrap();
+
+    let mut accepted = server.accept_channel().unwrap();
+    let error = accepted.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::FlowControlViolation(1)), "{:?}", error);
+}
+
+#[test]
+fn blocked_readers_do_not_hold_up_other_channels_test() {
+    let (left, right) = memory::duplex();
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false);
+    let client = stream::Multiplexer::try_new_cloned(config.clone(), stream::FlowControl::default(), left).unwrap();
+    let server = stream::Multiplexer::try_new_cloned(config, stream::FlowControl::default(), right).unwrap();
+    let mut client_channels: Vec<_> = (1..4).map(|id| client.open_channel(id).unwrap()).collect();
+    let mut server_channels: Vec<_> = (1..4).map(|_| server.accept_channel().unwrap()).collect();
+
+    // each side has a thread waiting on a channel nothing has been sent on yet
+    let mut client_first = client_channels.remove(0);
+    let mut server_second = server_channels.remove(1);
+    let client_reader = thread::spawn(move || client_first.read_next_message());
+    let server_reader = thread::spawn(move || server_second.read_next_message());
+    thread::sleep(Duration::from_millis(20));
+
+    send(&mut client_channels[1], b"ping");
+    assert_eq!(server_channels[1].read_next_message(), Ok(Vec::from("ping")));
+    send(&mut server_channels[1], b"pong");
+    assert_eq!(client_channels[1].read_next_message(), Ok(Vec::from("pong")));
+
+    send(&mut server_channels[0], b"for the client");
+    send(&mut client_channels[0], b"for the server");
+    assert_eq!(client_reader.join().unwrap(), Ok(Vec::from("for the client")));
+    assert_eq!(server_reader.join().unwrap(), Ok(Vec::from("for the server")));
+}
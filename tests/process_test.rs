@@ -0,0 +1,75 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false)
+}
+
+fn shell(script: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    command
+}
+
+#[test]
+fn echo_child_test() {
+    // cat sends every frame straight back
+    let mut child = stream::ProcessMessenger::spawn(Command::new("cat"), config()).unwrap();
+    for message in &["one", "two"] {
+        assert!(child.write(message.as_bytes()).unwrap() > message.len());
+    }
+    child.flush().unwrap();
+    assert_eq!(child.read_next_message(), Ok(Vec::from("one")));
+    assert_eq!(child.read_next_message(), Ok(Vec::from("two")));
+
+    child.close(Some(5)).unwrap();
+    assert!(child.write(b"too late").is_err());
+    let error = child.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::Closed { reason: Some(5) }), "{:?}", error);
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn child_dying_mid_frame_test() {
+    let mut child = stream::ProcessMessenger::spawn(shell("printf -- '--bound5--abc'; exit 3"), config()).unwrap();
+    match *child.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::ProcessDied { code } => assert_eq!(code, Some(3)),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn child_exiting_between_frames_test() {
+    let mut child = stream::ProcessMessenger::spawn(shell("printf -- '--bound2--hi--endbound--'"), config()).unwrap();
+    assert_eq!(child.read_next_message(), Ok(Vec::from("hi")));
+    assert_eq!(child.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn stderr_is_forwarded_test() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let collected = lines.clone();
+    let mut child = stream::ProcessMessenger::spawn_with_stderr(shell("echo first >&2; echo second >&2"), config(), move |line| {
+        collected.lock().unwrap().push(line);
+    }).unwrap();
+
+    assert!(child.wait().unwrap().success());
+    assert_eq!(*lines.lock().unwrap(), vec!["first".to_string(), "second".to_string()]);
+}
+
+#[test]
+fn child_is_killed_on_drop_test() {
+    let child = stream::ProcessMessenger::spawn(shell("exec sleep 30"), config()).unwrap();
+    let id = child.id();
+    drop(child);
+
+    let alive = Command::new("kill").arg("-0").arg(id.to_string()).stderr(Stdio::null()).status().unwrap();
+    assert!(!alive.success());
+}
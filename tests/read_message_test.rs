@@ -1,51 +1,42 @@
 extern crate messenger_plus;
 
 use messenger_plus::stream;
+use messenger_plus::stream::memory::{self, MemoryStream};
 
-use std::io;
+use std::io::{self, Read, Write};
 use std::mem;
 
-#[derive(Debug)]
-struct RandomRead {
-    info: Vec<u8>,
-}
-
-impl RandomRead {
-
-    fn new(message: &str, num_payloads: i32) -> RandomRead {
-        let mut data: Vec<u8> = Vec::new();
-        for _ in 0..num_payloads {
-            data.append(&mut Vec::from("--boundary"));
-            data.append(&mut Vec::from(mem::size_of_val(message.as_bytes()).to_string().as_bytes()));
-            data.append(&mut Vec::from("--"));
-            data.append(&mut Vec::from(message));
-            data.append(&mut Vec::from("--endboundary--"));
-        }
-        RandomRead {
-            info: data,
-        }
+/// Returns a stream holding `num_payloads` frames of `message`
+fn frames(message: &str, num_payloads: i32) -> MemoryStream {
+    let mut stream = memory::loopback();
+    for _ in 0..num_payloads {
+        write!(stream, "--boundary{}--{}--endboundary--", mem::size_of_val(message.as_bytes()), message).unwrap();
     }
+    stream
+}
 
+/// A stream whose first read is interrupted before it returns anything
+struct InterruptedOnce {
+    interrupted: bool,
+    stream: MemoryStream,
 }
 
-impl io::Read for RandomRead {
+impl Read for InterruptedOnce {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        for i in 0..buf.len() {
-            if self.info.is_empty() {
-                return Ok(i as usize);
-            }
-            buf[i] = self.info.remove(0);
+        if !self.interrupted {
+            self.interrupted = true;
+            return Err(io::Error::from(io::ErrorKind::Interrupted));
         }
-        Ok(buf.len())
+        self.stream.read(buf)
     }
 }
 
 #[test]
 fn read_next_message_test() {
     let payload_one = "payload_one";
-    let mut data = RandomRead::new(payload_one, 1);
+    let data = frames(payload_one, 1);
 
-    let mut message_reader: messenger_plus::stream::MessageReader<RandomRead> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+    let mut message_reader: messenger_plus::stream::MessageReader<MemoryStream> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
 
     assert_eq!(message_reader.read_next_message(), Ok(Vec::from(payload_one)));
 }
@@ -53,8 +44,8 @@ fn read_next_message_test() {
 #[test]
 fn special_characters_test() {
     let payload_one = "!@#$%^&*()_+-=[]{}|;:/?><";
-    let mut data = RandomRead::new(payload_one, 1);
-    let mut message_reader: messenger_plus::stream::MessageReader<RandomRead> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+    let data = frames(payload_one, 1);
+    let mut message_reader: messenger_plus::stream::MessageReader<MemoryStream> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
 
     assert_eq!(message_reader.read_next_message(), Ok(Vec::from(payload_one)));
 }
@@ -63,9 +54,9 @@ fn special_characters_test() {
 fn read_multiple_payloads_test() {
     let payload_one = "payload_one";
     let num_payloads = 3;
-    let mut data = RandomRead::new(payload_one, num_payloads);
+    let data = frames(payload_one, num_payloads);
 
-    let mut message_reader: messenger_plus::stream::MessageReader<RandomRead> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+    let mut message_reader: messenger_plus::stream::MessageReader<MemoryStream> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
 
     for _ in 0..num_payloads {
         assert_eq!(message_reader.read_next_message(), Ok(Vec::from(payload_one)));
@@ -74,8 +65,45 @@ fn read_multiple_payloads_test() {
 
 #[test]
 fn read_empty_payload_test() {
-    let mut data = RandomRead::new("", 0);
-    let mut message_reader: messenger_plus::stream::MessageReader<RandomRead> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+    let data = frames("", 0);
+    let mut message_reader: messenger_plus::stream::MessageReader<MemoryStream> = messenger_plus::stream::MessageReader::new("--", "boundary", "endboundary", data, false);
 
     assert_eq!(message_reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
-}
\ No newline at end of file
+}
+
+#[test]
+fn interrupted_first_byte_is_retried_test() {
+    let data = InterruptedOnce { interrupted: false, stream: frames("payload_one", 1) };
+    let mut message_reader = stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+
+    assert_eq!(message_reader.read_next_message(), Ok(Vec::from("payload_one")));
+}
+
+#[test]
+fn length_beyond_stream_test() {
+    let mut data = memory::loopback();
+    data.write_all(b"--boundary18446744073709551615--x--endboundary--").unwrap();
+    let mut message_reader = stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+
+    assert!(message_reader.read_next_message().is_err());
+}
+
+#[test]
+fn wrong_beginning_boundary_test() {
+    let mut data = memory::loopback();
+    data.write_all(b"--other5--first--endother----boundary6--second--endboundary--").unwrap();
+    let mut message_reader = stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+
+    let error = message_reader.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownBoundary(ref boundary) if boundary == "other"), "{:?}", error);
+    assert_eq!(message_reader.read_next_message(), Ok(Vec::from("second")));
+}
+
+#[test]
+fn heartbeat_frames_are_skipped_test() {
+    let mut data = memory::loopback();
+    data.write_all(b"--boundary!ping3--7----endboundary!ping----boundary!pong3--7----endboundary!pong----boundary5--after--endboundary--").unwrap();
+    let mut message_reader = stream::MessageReader::new("--", "boundary", "endboundary", data, false);
+
+    assert_eq!(message_reader.read_next_message(), Ok(Vec::from("after")));
+}
@@ -0,0 +1,164 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false)
+}
+
+fn quick_backoff(max_attempts: Option<usize>) -> stream::Backoff {
+    stream::Backoff::new(Duration::from_millis(1), Duration::from_millis(5), max_attempts)
+}
+
+fn send(messenger: &mut stream::DualMessenger<TcpStream>, message: &[u8]) {
+    assert!(messenger.write(message).unwrap() > message.len());
+}
+
+#[test]
+fn reconnects_after_peer_restart_test() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // each connection gets the handshake reply and one message, then the server drops it
+    let server = thread::spawn(move || {
+        for connection in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut messenger = stream::DualMessenger::new_from_config(config(), stream);
+            assert_eq!(messenger.read_next_message(), Ok(Vec::from("hello")));
+            send(&mut messenger, format!("connection {}", connection).as_bytes());
+        }
+    });
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let recorded = events.clone();
+    let mut client = stream::ReconnectingMessenger::new(config(), quick_backoff(None), move || TcpStream::connect(address))
+        .with_handshake(|messenger| {
+            assert!(messenger.write(b"hello")? > 5);
+            Ok(messenger.flush()?)
+        })
+        .with_events(move |event| recorded.borrow_mut().push(match *event {
+            stream::ReconnectEvent::Disconnected(_) => "disconnected",
+            stream::ReconnectEvent::ConnectFailed { .. } => "failed",
+            stream::ReconnectEvent::Connected { .. } => "connected",
+        }));
+
+    assert!(!client.is_connected());
+    assert_eq!(client.read_next_message(), Ok(Vec::from("connection 0")));
+    assert_eq!(client.read_next_message(), Ok(Vec::from("connection 1")));
+    server.join().unwrap();
+
+    assert_eq!(*events.borrow(), vec!["connected", "disconnected", "connected"]);
+}
+
+#[test]
+fn failed_send_returns_message_test() {
+    let attempts = Rc::new(RefCell::new(0));
+    let counted = attempts.clone();
+    let mut client = stream::ReconnectingMessenger::<TcpStream>::new(config(), quick_backoff(Some(3)), move || {
+        *counted.borrow_mut() += 1;
+        Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+    });
+
+    let failed = client.send(Vec::from("important")).unwrap_err();
+    assert_eq!(failed.error(), &stream::Error::from(stream::ErrorKind::Disconnected));
+    assert_eq!(failed.into_message(), Vec::from("important"));
+    assert_eq!(*attempts.borrow(), 3);
+}
+
+#[test]
+fn failed_handshake_is_retried_test() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut connections = 0;
+        loop {
+            let (stream, _) = listener.accept().unwrap();
+            connections += 1;
+            if let Ok(message) = stream::DualMessenger::new_from_config(config(), stream).read_next_message() {
+                return (connections, message);
+            }
+        }
+    });
+
+    let handshakes = Rc::new(RefCell::new(0));
+    let counted = handshakes.clone();
+    let mut client = stream::ReconnectingMessenger::new(config(), quick_backoff(None), move || TcpStream::connect(address))
+        .with_handshake(move |_| {
+            *counted.borrow_mut() += 1;
+            match *counted.borrow() {
+                1 => Err(stream::Error::from(stream::ErrorKind::TimedOut)),
+                _ => Ok(()),
+            }
+        });
+
+    // the first connection is dropped by the failed handshake, so the message arrives on the second
+    assert!(client.send(Vec::from("sent")).unwrap() > 4);
+    assert_eq!(*handshakes.borrow(), 2);
+    assert_eq!(server.join().unwrap(), (2, Vec::from("sent")));
+}
+
+#[test]
+fn closed_connection_is_not_reconnected_test() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut messenger = stream::DualMessenger::new_from_config(config(), stream);
+        assert_eq!(messenger.read_next_message(), Ok(Vec::from("hello")));
+        let error = messenger.read_next_message().unwrap_err();
+        assert!(matches!(*error.kind(), stream::ErrorKind::Closed { reason: Some(1) }), "{:?}", error);
+    });
+
+    let connections = Rc::new(RefCell::new(0));
+    let counted = connections.clone();
+    let mut client = stream::ReconnectingMessenger::new(config(), quick_backoff(None), move || {
+        *counted.borrow_mut() += 1;
+        TcpStream::connect(address)
+    });
+
+    assert!(client.send(Vec::from("hello")).unwrap() > 5);
+    client.messenger().unwrap().close(Some(1)).unwrap();
+    let failed = client.send(Vec::from("too late")).unwrap_err();
+    assert!(matches!(*failed.error().kind(), stream::ErrorKind::Closed { reason: None }), "{:?}", failed.error());
+    assert!(client.is_connected());
+    assert_eq!(*connections.borrow(), 1);
+    server.join().unwrap();
+}
+
+#[test]
+fn session_resumes_on_taken_connection_test() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut session = stream::SessionMessenger::new(3, stream::DualMessenger::new_from_config(config(), stream), 16);
+        assert_eq!(session.receive(), Ok(Vec::from("one")));
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut messenger = stream::DualMessenger::new_from_config(config(), stream);
+        let request = stream::SessionMessenger::read_resume(&mut messenger).unwrap();
+        session.resume_from(messenger, request).unwrap();
+        assert_eq!(session.receive(), Ok(Vec::from("two")));
+    });
+
+    let connections = Rc::new(RefCell::new(0));
+    let counted = connections.clone();
+    let mut client = stream::ReconnectingMessenger::new(config(), quick_backoff(None), move || {
+        *counted.borrow_mut() += 1;
+        TcpStream::connect(address)
+    });
+
+    let mut session = stream::SessionMessenger::new(3, client.take_messenger().unwrap(), 16);
+    session.send(b"one").unwrap();
+    assert!(!client.is_connected());
+    session.resume(client.take_messenger().unwrap()).unwrap();
+    session.send(b"two").unwrap();
+    server.join().unwrap();
+    assert_eq!(*connections.borrow(), 2);
+}
@@ -0,0 +1,170 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::io::{Read, Write, Result};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+/// Faults applied to the frames written through one end of a pipe, counted from 0
+#[derive(Default)]
+struct Faults {
+    frames_written: usize,
+    dropped: HashSet<usize>,
+    corrupted: HashSet<usize>,
+}
+
+/// One end of an in-memory link that delivers whole frames, each completed by a flush
+struct LossyEnd {
+    incoming: Rc<RefCell<VecDeque<u8>>>,
+    outgoing: Rc<RefCell<VecDeque<u8>>>,
+    pending: Vec<u8>,
+    faults: Rc<RefCell<Faults>>,
+}
+
+fn lossy_pipe() -> (LossyEnd, LossyEnd, Rc<RefCell<Faults>>) {
+    let left = Rc::new(RefCell::new(VecDeque::new()));
+    let right = Rc::new(RefCell::new(VecDeque::new()));
+    let faults = Rc::new(RefCell::new(Faults::default()));
+    (
+        LossyEnd { incoming: left.clone(), outgoing: right.clone(), pending: Vec::new(), faults: faults.clone() },
+        LossyEnd { incoming: right, outgoing: left, pending: Vec::new(), faults: Rc::new(RefCell::new(Faults::default())) },
+        faults,
+    )
+}
+
+impl Write for LossyEnd {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut faults = self.faults.borrow_mut();
+        let frame = faults.frames_written;
+        faults.frames_written += 1;
+        if faults.dropped.contains(&frame) {
+            self.pending.clear();
+            return Ok(());
+        }
+        if faults.corrupted.contains(&frame) {
+            // the last byte of the message sits just before the trailer
+            let index = self.pending.len() - "--endbound--".len() - 1;
+            self.pending[index] ^= 0xff;
+        }
+        self.outgoing.borrow_mut().extend(self.pending.drain(..));
+        Ok(())
+    }
+}
+
+impl Read for LossyEnd {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut incoming = self.incoming.borrow_mut();
+        if incoming.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        let count = buf.len().min(incoming.len());
+        for (byte, value) in buf.iter_mut().zip(incoming.drain(..count)) {
+            *byte = value;
+        }
+        Ok(count)
+    }
+}
+
+fn reliable_pair(hashing_enabled: bool, reliability: stream::Reliability) -> (stream::ReliableMessenger<LossyEnd>, stream::ReliableMessenger<LossyEnd>, Rc<RefCell<Faults>>) {
+    let (left, right, faults) = lossy_pipe();
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", hashing_enabled);
+    (
+        stream::ReliableMessenger::new(stream::DualMessenger::new_from_config(config.clone(), left), reliability),
+        stream::ReliableMessenger::new(stream::DualMessenger::new_from_config(config, right), reliability),
+        faults,
+    )
+}
+
+#[test]
+fn in_order_delivery_test() {
+    let (mut sender, mut receiver, _) = reliable_pair(false, stream::Reliability::default());
+    for message in &["one", "two", "three"] {
+        sender.send(message.as_bytes()).unwrap();
+    }
+    assert_eq!(sender.unacknowledged(), 3);
+
+    assert_eq!(receiver.receive(), Ok(Vec::from("one")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("two")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("three")));
+    assert!(receiver.receive().is_err());
+
+    sender.poll().unwrap();
+    assert_eq!(sender.unacknowledged(), 0);
+}
+
+#[test]
+fn lost_frame_is_retransmitted_test() {
+    let (mut sender, mut receiver, faults) = reliable_pair(false, stream::Reliability::new(8, Duration::from_millis(20)));
+    faults.borrow_mut().dropped.insert(1);
+    for message in &["a", "b", "c"] {
+        sender.send(message.as_bytes()).unwrap();
+    }
+
+    assert_eq!(receiver.receive(), Ok(Vec::from("a")));
+    // "c" arrives after a gap, so it is dropped until "b" is sent again
+    assert!(receiver.receive().is_err());
+    sender.poll().unwrap();
+    assert_eq!(sender.unacknowledged(), 2);
+
+    thread::sleep(Duration::from_millis(30));
+    sender.poll().unwrap();
+    assert_eq!(receiver.receive(), Ok(Vec::from("b")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("c")));
+    assert!(receiver.receive().is_err());
+
+    sender.poll().unwrap();
+    assert_eq!(sender.unacknowledged(), 0);
+}
+
+#[test]
+fn corrupted_frame_is_retransmitted_test() {
+    let (mut sender, mut receiver, faults) = reliable_pair(true, stream::Reliability::new(8, Duration::from_secs(60)));
+    faults.borrow_mut().corrupted.insert(0);
+    sender.send(b"fragile").unwrap();
+    sender.send(b"sturdy").unwrap();
+
+    // the corrupted frame is reported straight away rather than after the timeout
+    assert!(receiver.receive().is_err());
+    sender.poll().unwrap();
+    assert_eq!(receiver.receive(), Ok(Vec::from("fragile")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("sturdy")));
+}
+
+#[test]
+fn duplicates_are_delivered_once_test() {
+    let (mut sender, mut receiver, faults) = reliable_pair(false, stream::Reliability::new(8, Duration::from_millis(1)));
+    faults.borrow_mut().dropped.insert(1);
+    sender.send(b"first").unwrap();
+    sender.send(b"second").unwrap();
+    thread::sleep(Duration::from_millis(5));
+    // both messages are sent again, so "first" arrives twice
+    sender.poll().unwrap();
+
+    assert_eq!(receiver.receive(), Ok(Vec::from("first")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("second")));
+    assert!(receiver.receive().is_err());
+}
+
+#[test]
+fn send_window_test() {
+    let (mut sender, mut receiver, _) = reliable_pair(false, stream::Reliability::new(2, Duration::from_secs(60)));
+    sender.send(b"1").unwrap();
+    sender.send(b"2").unwrap();
+    assert_eq!(receiver.receive(), Ok(Vec::from("1")));
+
+    // the acknowledgement for "1" makes room for "3"
+    sender.send(b"3").unwrap();
+    assert_eq!(sender.unacknowledged(), 2);
+    assert_eq!(receiver.receive(), Ok(Vec::from("2")));
+    assert_eq!(receiver.receive(), Ok(Vec::from("3")));
+}
@@ -0,0 +1,38 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::{Cursor, Write};
+
+#[test]
+fn error_frame_test() {
+    let mut writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    assert!(writer.write(b"before").unwrap() > 6);
+    assert!(writer.send_error(404, "no such record").unwrap() > 14);
+    assert!(writer.write(b"after").unwrap() > 5);
+
+    let mut reader = stream::MessageReader::new("--", "bound", "endbound", Cursor::new(writer.get_writer().clone()), false);
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("before")));
+    match *reader.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::Remote { code, ref message } => {
+            assert_eq!(code, 404);
+            assert_eq!(message, "no such record");
+        }
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    // the error frame is consumed, so the stream carries on
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("after")));
+}
+
+#[test]
+fn remote_error_is_not_a_local_error_test() {
+    let mut sender = stream::DualMessenger::new("--", "bound", "endbound", Cursor::new(Vec::new()), false);
+    assert!(sender.send_error(7, "").unwrap() > 0);
+    let written = sender.release().into_inner();
+    let mut messenger = stream::DualMessenger::new("--", "bound", "endbound", Cursor::new(written), false);
+
+    let error = messenger.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::Remote { code: 7, ref message } if message.is_empty()), "{:?}", error);
+    assert!(error != stream::Error::from(stream::ErrorKind::UnknownBoundary(String::new())));
+    assert_eq!(error.to_string(), "The peer reported error 7: ");
+}
@@ -0,0 +1,258 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false)
+}
+
+fn start_server() -> stream::RpcClient<UnixStream> {
+    let (client_stream, server_stream) = UnixStream::pair().unwrap();
+    thread::spawn(move || {
+        let mut server = stream::RpcServer::new(stream::DualMessenger::new_from_config(config(), server_stream));
+        server
+            .register("echo", |request| request)
+            .register("reverse", |mut request| {
+                request.reverse();
+                request
+            })
+            .register("slow", |request| {
+                thread::sleep(Duration::from_millis(300));
+                request
+            })
+            .register_streaming("count", |request, stream| {
+                let up_to: u32 = String::from_utf8(request).unwrap().parse().map_err(|_| String::from("not a number"))?;
+                for i in 0..up_to {
+                    stream.send(i.to_string().as_bytes()).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            })
+            .register_streaming("numbered", |_, stream| {
+                let mut count = 0;
+                while let Some(item) = stream.recv().map_err(|e| e.to_string())? {
+                    count += 1;
+                    stream.send(format!("{}: {}", count, String::from_utf8_lossy(&item)).as_bytes()).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            })
+            .register_streaming("tail", |_, stream| {
+                loop {
+                    stream.send(b"line").map_err(|e| e.to_string())?;
+                }
+            });
+        server.run().unwrap();
+    });
+
+    let reader = stream::MessageReader::new_from_config(config(), client_stream.try_clone().unwrap());
+    let writer = stream::MessageWriter::new_from_config(config(), client_stream);
+    stream::RpcClient::new(reader, writer)
+}
+
+#[test]
+fn unary_call_test() {
+    let client = start_server();
+
+    assert_eq!(client.call("echo", b"hello, world!"), Ok(Vec::from("hello, world!")));
+    assert_eq!(client.call("reverse", b"abc"), Ok(Vec::from("cba")));
+    assert_eq!(client.call("echo", b""), Ok(Vec::new()));
+}
+
+#[test]
+fn unknown_method_test() {
+    let client = start_server();
+
+    let error = client.call("missing", b"?").unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownMethod(ref method) if method == "missing"), "{:?}", error);
+    assert_eq!(client.call("echo", b"still works"), Ok(Vec::from("still works")));
+}
+
+#[test]
+fn calls_in_flight_test() {
+    let client = start_server();
+    let first = client.start_call("echo", b"first").unwrap();
+    let second = client.start_call("reverse", b"second").unwrap();
+    let third = client.start_call("echo", b"third").unwrap();
+    assert!(first.id() != second.id());
+
+    assert_eq!(third.wait(), Ok(Vec::from("third")));
+    assert_eq!(first.wait(), Ok(Vec::from("first")));
+    assert_eq!(second.wait(), Ok(Vec::from("dnoces")));
+}
+
+#[test]
+fn calls_from_many_threads_test() {
+    let client = Arc::new(start_server());
+    let workers: Vec<_> = (0..8).map(|worker| {
+        let client = client.clone();
+        thread::spawn(move || {
+            for call in 0..20 {
+                let request = format!("{}-{}", worker, call);
+                assert_eq!(client.call("echo", request.as_bytes()), Ok(request.into_bytes()));
+            }
+        })
+    }).collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+#[test]
+fn timeout_test() {
+    let mut client = start_server();
+    client.set_timeout(Duration::from_millis(50));
+
+    assert_eq!(client.call("slow", b"late"), Err(stream::Error::from(stream::ErrorKind::TimedOut)));
+    client.set_timeout(Duration::from_secs(5));
+    assert_eq!(client.call("echo", b"on time"), Ok(Vec::from("on time")));
+}
+
+#[test]
+fn disconnected_test() {
+    let (client_stream, server_stream) = UnixStream::pair().unwrap();
+    let reader = stream::MessageReader::new_from_config(config(), client_stream.try_clone().unwrap());
+    let writer = stream::MessageWriter::new_from_config(config(), client_stream);
+    let client = stream::RpcClient::new(reader, writer);
+    drop(server_stream);
+
+    // depending on which side notices first this is a broken pipe or `Disconnected`
+    assert!(client.call("echo", b"nobody home").is_err());
+}
+
+#[test]
+fn server_stream_test() {
+    let client = start_server();
+    let items: Vec<_> = client.start_stream("count", b"4").unwrap().collect();
+
+    assert_eq!(items, vec![Ok(Vec::from("0")), Ok(Vec::from("1")), Ok(Vec::from("2")), Ok(Vec::from("3"))]);
+    assert_eq!(client.start_stream("count", b"0").unwrap().count(), 0);
+}
+
+#[test]
+fn stream_error_test() {
+    let client = start_server();
+    let mut items = client.start_stream("count", b"many").unwrap();
+
+    let error = items.next().unwrap().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::StreamFailed(ref reason) if reason == "not a number"), "{:?}", error);
+    assert_eq!(items.next(), None);
+    let error = client.start_stream("missing", b"").unwrap().next().unwrap().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownMethod(ref method) if method == "missing"), "{:?}", error);
+}
+
+#[test]
+fn cancel_stream_test() {
+    let client = start_server();
+    let mut items = client.start_stream("count", b"1000").unwrap();
+    assert_eq!(items.next(), Some(Ok(Vec::from("0"))));
+    items.cancel().unwrap();
+
+    let mut numbered = client.start_stream("numbered", b"").unwrap();
+    numbered.send(b"first").unwrap();
+    assert_eq!(numbered.next(), Some(Ok(Vec::from("1: first"))));
+    drop(numbered);
+
+    assert_eq!(client.call("echo", b"still works"), Ok(Vec::from("still works")));
+}
+
+#[test]
+fn send_only_handler_sees_cancel_test() {
+    let client = start_server();
+    let mut lines = client.start_stream("tail", b"").unwrap();
+    assert_eq!(lines.next(), Some(Ok(Vec::from("line"))));
+    lines.cancel().unwrap();
+
+    // the handler never reads, so only its sends can find the cancellation and free the server
+    let pending = client.start_call("echo", b"after the tail").unwrap();
+    assert_eq!(pending.wait_timeout(Duration::from_secs(5)), Ok(Vec::from("after the tail")));
+}
+
+#[test]
+fn bidirectional_stream_test() {
+    let client = start_server();
+    let mut numbered = client.start_stream("numbered", b"").unwrap();
+    numbered.send(b"a").unwrap();
+    numbered.send(b"b").unwrap();
+    assert_eq!(numbered.next_timeout(Duration::from_secs(5)), Ok(Some(Vec::from("1: a"))));
+    numbered.send(b"c").unwrap();
+    numbered.finish_sending().unwrap();
+    assert!(numbered.send(b"d").is_err());
+
+    let rest: Vec<_> = numbered.collect();
+    assert_eq!(rest, vec![Ok(Vec::from("2: b")), Ok(Vec::from("3: c"))]);
+}
+
+#[test]
+fn unary_method_as_stream_test() {
+    let client = start_server();
+    let items: Vec<_> = client.start_stream("reverse", b"abc").unwrap().collect();
+
+    assert_eq!(items, vec![Ok(Vec::from("cba"))]);
+    assert_eq!(client.call("count", b"3"), Ok(Vec::from("0")));
+}
+
+/// Returns a client along with the raw stream its server side would use
+fn client_and_raw_server() -> (stream::RpcClient<UnixStream>, UnixStream) {
+    let (client_stream, server_stream) = UnixStream::pair().unwrap();
+    let reader = stream::MessageReader::new_from_config(config(), client_stream.try_clone().unwrap());
+    let writer = stream::MessageWriter::new_from_config(config(), client_stream);
+    (stream::RpcClient::new(reader, writer), server_stream)
+}
+
+#[test]
+fn bad_frames_are_skipped_test() {
+    let (client, mut server_stream) = client_and_raw_server();
+    let pending = client.start_call("echo", b"pong").unwrap();
+
+    // a response with the wrong ending, bytes that are no frame at all and a response without its header
+    server_stream.write_all(b"--bound!response7--0--0--x--endbound!oops--").unwrap();
+    server_stream.write_all(b"junk").unwrap();
+    server_stream.write_all(b"--bound!response4--pong--endbound!response--").unwrap();
+    let mut server = stream::MessageWriter::new_from_config(config(), server_stream);
+    assert!(server.write_between("bound!response", "endbound!response", b"0--0--pong").unwrap() > 10);
+
+    assert_eq!(pending.wait(), Ok(Vec::from("pong")));
+}
+
+#[test]
+fn pending_calls_fail_when_stream_ends_test() {
+    let (client, server_stream) = client_and_raw_server();
+    let first = client.start_call("echo", b"one").unwrap();
+    let second = client.start_call("echo", b"two").unwrap();
+    drop(server_stream);
+
+    assert_eq!(first.wait(), Err(stream::Error::from(stream::ErrorKind::Disconnected)));
+    assert_eq!(second.wait(), Err(stream::Error::from(stream::ErrorKind::Disconnected)));
+    assert_eq!(client.call("echo", b"three"), Err(stream::Error::from(stream::ErrorKind::Disconnected)));
+}
+
+#[test]
+fn server_skips_bad_frames_test() {
+    let (mut client_stream, server_stream) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || {
+        let mut server = stream::RpcServer::new(stream::DualMessenger::new_from_config(config(), server_stream));
+        server.register("echo", |request| request);
+        server.run()
+    });
+
+    // a frame that is not a request and a request without its header
+    client_stream.write_all(b"--bound!note5--aside--endbound!note--").unwrap();
+    client_stream.write_all(b"--bound!request1--x--endbound!request--").unwrap();
+    let closer = client_stream.try_clone().unwrap();
+    let reader = stream::MessageReader::new_from_config(config(), client_stream.try_clone().unwrap());
+    let writer = stream::MessageWriter::new_from_config(config(), client_stream);
+    let client = stream::RpcClient::new(reader, writer);
+
+    let pending = client.start_call("echo", b"heard").unwrap();
+    assert_eq!(pending.wait_timeout(Duration::from_secs(5)), Ok(Vec::from("heard")));
+    closer.shutdown(Shutdown::Both).unwrap();
+    assert_eq!(server.join().unwrap(), Ok(()));
+}
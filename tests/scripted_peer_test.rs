@@ -0,0 +1,98 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+use messenger_plus::testing::ScriptedPeer;
+
+use std::io::Write;
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", true).with_sequence_numbers(true)
+}
+
+fn send<W: Write>(writer: &mut W, message: &[u8]) {
+    assert!(writer.write(message).unwrap() > message.len());
+}
+
+/// The code under test: greets the service, echoes back whatever it answers and says goodbye
+fn greet<T: std::io::Read + Write>(messenger: &mut stream::DualMessenger<T>) -> Vec<u8> {
+    send(messenger, b"hello");
+    let answer = messenger.read_next_message().unwrap();
+    send(messenger, &answer);
+    messenger.close(Some(0)).unwrap();
+    answer
+}
+
+#[test]
+fn script_is_played_test() {
+    let peer = ScriptedPeer::new(config())
+        .expect("hello")
+        .send("welcome")
+        .expect("welcome")
+        .expect_close(Some(0));
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+
+    assert_eq!(greet(&mut messenger), b"welcome");
+    assert!(messenger.release().is_finished());
+}
+
+#[test]
+fn sends_run_until_next_expectation_test() {
+    let peer = ScriptedPeer::new(config())
+        .send("first")
+        .send_between("note", "endnote", "aside")
+        .send_error(404, "not found")
+        .expect_between("request", "endrequest", "get");
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("first")));
+    let error = messenger.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownBoundary(ref boundary) if boundary == "note"), "{:?}", error);
+    match *messenger.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::Remote { code, ref message } => assert_eq!((code, message.as_str()), (404, "not found")),
+        ref kind => panic!("unexpected error {:?}", kind),
+    }
+    assert!(messenger.write_between("request", "endrequest", b"get").unwrap() > 3);
+    assert_eq!(messenger.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+}
+
+#[test]
+#[should_panic(expected = "step 1 of the script (expect \"hello\") failed: the message written does not match\nexpected: \"hello\"\n written: \"help\"\n              ^ first difference at byte 3")]
+fn mismatch_shows_diff_test() {
+    let peer = ScriptedPeer::new(config()).expect("hello");
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+    send(&mut messenger, b"help");
+}
+
+#[test]
+#[should_panic(expected = "a frame beginning with `other` was written instead")]
+fn wrong_boundaries_test() {
+    let peer = ScriptedPeer::new(config()).expect("hello");
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+    let _ = messenger.write_between("other", "endother", b"hello");
+}
+
+#[test]
+#[should_panic(expected = "the code under test read before writing what was expected")]
+fn read_while_expecting_test() {
+    let peer = ScriptedPeer::new(config()).expect("hello").send("welcome");
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+    let _ = messenger.read_next_message();
+}
+
+#[test]
+#[should_panic(expected = "the script had already finished: the message \"extra\" was written")]
+fn write_after_script_test() {
+    let peer = ScriptedPeer::new(config()).expect("hello");
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+    send(&mut messenger, b"hello");
+    send(&mut messenger, b"extra");
+}
+
+#[test]
+#[should_panic(expected = "step 3 of the script (expect \"bye\") failed: the stream was dropped before the script finished")]
+fn unfinished_script_test() {
+    let peer = ScriptedPeer::new(config()).expect("hello").send("welcome").expect("bye");
+    let mut messenger = stream::DualMessenger::new_from_config(config(), peer);
+    send(&mut messenger, b"hello");
+    let _ = messenger.read_next_message();
+}
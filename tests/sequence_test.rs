@@ -0,0 +1,77 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::{Cursor, Write};
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false).with_sequence_numbers(true)
+}
+
+/// Writes frames carrying the given sequence numbers, as a lossy relay might deliver them
+fn frames(sequences: &[(u64, &str)]) -> Vec<u8> {
+    let mut writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    for &(sequence, message) in sequences {
+        let payload = format!("{}--{}", sequence, message);
+        writer.write_between("bound", "endbound", payload.as_bytes()).unwrap();
+    }
+    writer.get_writer().clone()
+}
+
+#[test]
+fn sequenced_round_trip_test() {
+    let mut writer = stream::MessageWriter::new_from_config(config(), Vec::new());
+    for message in &["first", "second", "third"] {
+        assert!(writer.write(message.as_bytes()).unwrap() > message.len());
+    }
+    assert!(writer.get_writer().starts_with(b"--bound8--0--first--endbound--"));
+
+    let mut reader = stream::MessageReader::new_from_config(config(), Cursor::new(writer.get_writer().clone()));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("first")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("second")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("third")));
+    assert_eq!(reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+}
+
+#[test]
+fn gap_test() {
+    let mut reader = stream::MessageReader::new_from_config(config(), Cursor::new(frames(&[(0, "a"), (3, "d"), (4, "e")])));
+
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("a")));
+    match *reader.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::SequenceGap { expected, received } => assert_eq!((expected, received), (1, 3)),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("d")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("e")));
+}
+
+#[test]
+fn duplicate_test() {
+    let mut reader = stream::MessageReader::new_from_config(config(), Cursor::new(frames(&[(0, "a"), (1, "b"), (1, "b"), (2, "c")])));
+
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("a")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("b")));
+    match *reader.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::DuplicateSequence(sequence) => assert_eq!(sequence, 1),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("c")));
+}
+
+#[test]
+fn out_of_order_test() {
+    let mut messenger = stream::DualMessenger::new_from_config(config(), Cursor::new(frames(&[(0, "a"), (2, "c"), (1, "b"), (1, "b")])));
+
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("a")));
+    let error = messenger.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::SequenceGap { expected: 1, received: 2 }), "{:?}", error);
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("c")));
+    match *messenger.read_next_message().unwrap_err().kind() {
+        stream::ErrorKind::OutOfOrderSequence(sequence) => assert_eq!(sequence, 1),
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("b")));
+    let error = messenger.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::DuplicateSequence(1)), "{:?}", error);
+}
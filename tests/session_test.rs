@@ -0,0 +1,104 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+fn connection() -> (stream::DualMessenger<UnixStream>, stream::DualMessenger<UnixStream>) {
+    let (left, right) = UnixStream::pair().unwrap();
+    (
+        stream::DualMessenger::new("--", "bound", "endbound", left, false),
+        stream::DualMessenger::new("--", "bound", "endbound", right, false),
+    )
+}
+
+/// Moves both ends of a session onto a fresh connection, with the server routing it by session ID
+fn reconnect(client: &mut stream::SessionMessenger<UnixStream>, server: stream::SessionMessenger<UnixStream>) -> stream::SessionMessenger<UnixStream> {
+    let (client_end, mut server_end) = connection();
+    let accepting = thread::spawn(move || {
+        let mut server = server;
+        let request = stream::SessionMessenger::read_resume(&mut server_end).unwrap();
+        assert_eq!(request.session_id(), server.session_id());
+        server.resume_from(server_end, request).unwrap();
+        server
+    });
+    client.resume(client_end).unwrap();
+    accepting.join().unwrap()
+}
+
+#[test]
+fn resume_resends_lost_messages_test() {
+    let (client_end, server_end) = connection();
+    let mut client = stream::SessionMessenger::new(7, client_end, 16);
+    let mut server = stream::SessionMessenger::new(7, server_end, 16);
+
+    for message in &["one", "two", "three"] {
+        client.send(message.as_bytes()).unwrap();
+    }
+    assert_eq!(server.receive(), Ok(Vec::from("one")));
+    server.send(b"reply").unwrap();
+    // the connection drops with "two" and "three" unread and the reply and acknowledgement unseen
+    let mut server = reconnect(&mut client, server);
+
+    assert_eq!(server.receive(), Ok(Vec::from("two")));
+    assert_eq!(server.receive(), Ok(Vec::from("three")));
+    assert_eq!(client.receive(), Ok(Vec::from("reply")));
+
+    client.send(b"four").unwrap();
+    assert_eq!(server.receive(), Ok(Vec::from("four")));
+    assert_eq!(server.unacknowledged(), 0);
+}
+
+#[test]
+fn received_messages_are_not_repeated_test() {
+    let (client_end, server_end) = connection();
+    let mut client = stream::SessionMessenger::new(1, client_end, 16);
+    let mut server = stream::SessionMessenger::new(1, server_end, 16);
+
+    client.send(b"once").unwrap();
+    assert_eq!(server.receive(), Ok(Vec::from("once")));
+    // the server's acknowledgement never reached the client, which still holds the message
+    assert_eq!(client.unacknowledged(), 1);
+    let mut server = reconnect(&mut client, server);
+    assert_eq!(client.unacknowledged(), 0);
+
+    client.send(b"twice").unwrap();
+    assert_eq!(server.receive(), Ok(Vec::from("twice")));
+}
+
+#[test]
+fn full_replay_buffer_waits_for_acknowledgements_test() {
+    let (client_end, server_end) = connection();
+    let mut client = stream::SessionMessenger::new(2, client_end, 2);
+    let receiving = thread::spawn(move || {
+        let mut server = stream::SessionMessenger::new(2, server_end, 2);
+        (0..5).map(|_| server.receive().unwrap()).collect::<Vec<_>>()
+    });
+
+    for index in 0..5 {
+        client.send(format!("message {}", index).as_bytes()).unwrap();
+        assert!(client.unacknowledged() <= 2);
+    }
+    assert_eq!(receiving.join().unwrap().len(), 5);
+}
+
+#[test]
+fn mismatched_session_is_refused_test() {
+    let (_, server_end) = connection();
+    let mut server = stream::SessionMessenger::new(3, server_end, 4);
+    let (other_end, mut new_server_end) = connection();
+    let other = thread::spawn(move || {
+        let (_, placeholder) = connection();
+        let mut other = stream::SessionMessenger::new(4, placeholder, 4);
+        other.resume(other_end)
+    });
+
+    let request = stream::SessionMessenger::read_resume(&mut new_server_end).unwrap();
+    assert_eq!(request.session_id(), 4);
+    let error = server.resume_from(new_server_end, request).unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::UnknownSession(4)), "{:?}", error);
+    // the refused connection is dropped, so the other side gives up too
+    assert!(other.join().unwrap().is_err());
+}
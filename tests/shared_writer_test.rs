@@ -0,0 +1,72 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::{Cursor, Write, Result};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A sink that yields between partial writes, giving other threads every chance to interleave
+#[derive(Clone, Default)]
+struct SlowSink {
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Write for SlowSink {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let count = buf.len().min(3);
+        self.written.lock().unwrap().extend_from_slice(&buf[..count]);
+        thread::yield_now();
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn frames_do_not_interleave_test() {
+    let sink = SlowSink::default();
+    let writer = stream::SharedMessageWriter::new(stream::MessageWriter::new("--", "bound", "endbound", sink.clone(), false));
+
+    let workers: Vec<_> = (0..8).map(|worker| {
+        let writer = writer.clone();
+        thread::spawn(move || {
+            for message in 0..25 {
+                let message = format!("worker {} message {}", worker, message);
+                assert!(writer.send(message.as_bytes()).unwrap() > message.len());
+            }
+        })
+    }).collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let written = sink.written.lock().unwrap().clone();
+    let mut reader = stream::MessageReader::new("--", "bound", "endbound", Cursor::new(written), false);
+    let mut next_message = [0; 8];
+    for _ in 0..200 {
+        let message = String::from_utf8(reader.read_next_message().unwrap()).unwrap();
+        let parts: Vec<usize> = message.split(' ').filter_map(|part| part.parse().ok()).collect();
+        // each worker's messages arrive whole and in the order it sent them
+        assert_eq!(parts[1], next_message[parts[0]]);
+        next_message[parts[0]] += 1;
+    }
+    assert_eq!(reader.read_next_message(), Err(stream::Error::from(stream::ErrorKind::BufferEmpty)));
+}
+
+#[test]
+fn close_applies_to_every_clone_test() {
+    let writer = stream::SharedMessageWriter::new(stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false));
+    let other = writer.clone();
+    writer.close(Some(9)).unwrap();
+
+    assert!(other.is_closed());
+    assert!(other.send(b"too late").is_err());
+
+    let writer = writer.try_into_inner().unwrap_err();
+    drop(other);
+    let inner = writer.try_into_inner().unwrap();
+    assert_eq!(inner.get_writer().as_slice(), b"--bound!close3--9----endbound!close--");
+}
@@ -0,0 +1,95 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+use messenger_plus::stream::memory::{self, MemoryStream};
+
+use std::io::Write;
+
+fn loopback_messenger() -> stream::DualMessenger<MemoryStream> {
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false).with_sequence_numbers(true);
+    stream::DualMessenger::new_from_config(config, memory::loopback())
+}
+
+fn send<W: Write>(writer: &mut W, message: &[u8]) {
+    assert!(writer.write(message).unwrap() > message.len());
+}
+
+#[test]
+fn split_and_reunite_test() {
+    let mut messenger = loopback_messenger();
+    send(&mut messenger, b"before");
+
+    let (mut reader, mut writer) = messenger.split();
+    send(&mut writer, b"during");
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("before")));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("during")));
+
+    // sequence numbers carry on across the split, so no gap is reported
+    let mut messenger = stream::DualMessenger::reunite(reader, writer).unwrap();
+    send(&mut messenger, b"after");
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("after")));
+}
+
+#[test]
+fn reunite_mismatched_halves_test() {
+    let (first_reader, _first_writer) = loopback_messenger().split();
+    let (_second_reader, second_writer) = loopback_messenger().split();
+
+    let (reader, writer) = *stream::DualMessenger::reunite(first_reader, second_writer).unwrap_err();
+    assert!(!writer.is_closed());
+    drop(reader);
+}
+
+#[test]
+fn reunite_cloned_mismatched_halves_test() {
+    let (first_reader, first_writer) = loopback_messenger().try_split().unwrap();
+    let (_second_reader, second_writer) = loopback_messenger().try_split().unwrap();
+
+    let (first_reader, _) = *stream::DualMessenger::reunite_cloned(first_reader, second_writer).unwrap_err();
+    assert!(stream::DualMessenger::reunite_cloned(first_reader, first_writer).is_ok());
+}
+
+#[test]
+fn closed_state_carries_over_test() {
+    let mut messenger = loopback_messenger();
+    messenger.close(Some(3)).unwrap();
+
+    let (mut reader, mut writer) = messenger.split();
+    assert!(writer.is_closed());
+    assert!(writer.write(b"too late").is_err());
+    let error = reader.read_next_message().unwrap_err();
+    assert!(matches!(*error.kind(), stream::ErrorKind::Closed { reason: Some(3) }), "{:?}", error);
+}
+
+#[cfg(unix)]
+#[test]
+fn halves_on_separate_threads_test() {
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    let (left, right) = UnixStream::pair().unwrap();
+    let echo = thread::spawn(move || {
+        let mut messenger = stream::DualMessenger::new("--", "bound", "endbound", right, false);
+        while let Ok(message) = messenger.read_next_message() {
+            send(&mut messenger, &message);
+        }
+    });
+
+    let messenger = stream::DualMessenger::new("--", "bound", "endbound", left, false);
+    let (mut reader, mut writer) = messenger.try_split().unwrap();
+    // the reader blocks on its own thread while the writer keeps sending
+    let receiving = thread::spawn(move || {
+        let received: Vec<_> = (0..3).map(|_| reader.read_next_message().unwrap()).collect();
+        (reader, received)
+    });
+    for message in &["one", "two", "three"] {
+        send(&mut writer, message.as_bytes());
+    }
+    let (reader, received) = receiving.join().unwrap();
+    assert_eq!(received, vec![Vec::from("one"), Vec::from("two"), Vec::from("three")]);
+
+    let mut messenger = stream::DualMessenger::reunite_cloned(reader, writer).unwrap();
+    messenger.close(None).unwrap();
+    drop(messenger);
+    echo.join().unwrap();
+}
@@ -0,0 +1,52 @@
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::{Cursor, Write};
+
+fn config() -> stream::StreamConfiguration {
+    stream::StreamConfiguration::new("--", "bound", "endbound", false)
+}
+
+#[test]
+fn messenger_from_halves_test() {
+    let mut incoming = stream::MessageWriter::new_from_config(config(), Vec::new());
+    assert!(incoming.write(b"from the reader").unwrap() > 15);
+
+    let mut messenger = stream::DualMessenger::from_halves(config(), Cursor::new(incoming.get_writer().clone()), Vec::new());
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("from the reader")));
+    assert!(messenger.write(b"to the writer").unwrap() > 13);
+
+    let (_, written) = messenger.release().into_inner();
+    let mut reader = stream::MessageReader::new_from_config(config(), Cursor::new(written));
+    assert_eq!(reader.read_next_message(), Ok(Vec::from("to the writer")));
+}
+
+#[cfg(unix)]
+#[test]
+fn stdio_plugin_test() {
+    use std::process::Command;
+
+    // the plugin is an example so that it is not installed with the crate, and is built here in the same profile as this test
+    let mut build = Command::new(env!("CARGO"));
+    build.args(["build", "--quiet", "--example", "stdio_plugin"]).current_dir(env!("CARGO_MANIFEST_DIR"));
+    if !cfg!(debug_assertions) {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success());
+    let plugin = std::env::current_exe().unwrap().parent().unwrap().parent().unwrap().join("examples").join("stdio_plugin");
+    let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let collected = lines.clone();
+    let mut child = stream::ProcessMessenger::spawn_with_stderr(Command::new(plugin), config(), move |line| {
+        collected.lock().unwrap().push(line);
+    }).unwrap();
+
+    for message in &["ping", "pong"] {
+        assert!(child.write(message.as_bytes()).unwrap() > message.len());
+        child.flush().unwrap();
+        // the plugin's println! went to stderr, so the frame arrives intact
+        assert_eq!(child.read_next_message(), Ok(Vec::from(*message)));
+    }
+    assert!(child.wait().unwrap().success());
+    assert_eq!(*lines.lock().unwrap(), vec!["plugin received 4 bytes".to_string(), "plugin received 4 bytes".to_string()]);
+}
@@ -0,0 +1,84 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut writer = stream::MessageWriter::new("--", "bound", "endbound", Vec::new(), false);
+    assert!(writer.write(message).unwrap() > message.len());
+    writer.get_writer().clone()
+}
+
+fn is_timeout(error: &stream::Error) -> bool {
+    match *error.kind() {
+        stream::ErrorKind::IOError(ref e) => e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut,
+        _ => false,
+    }
+}
+
+#[test]
+fn timeout_resumes_partial_frame_test() {
+    let (mut left, right) = UnixStream::pair().unwrap();
+    let mut reader = stream::MessageReader::new("--", "bound", "endbound", right, false);
+    let frame = frame(b"split across a timeout");
+    let (first, second) = frame.split_at(frame.len() / 2);
+
+    left.write_all(first).unwrap();
+    let error = reader.read_next_message_timeout(Duration::from_millis(50)).unwrap_err();
+    assert!(is_timeout(&error));
+
+    left.write_all(second).unwrap();
+    assert_eq!(reader.read_next_message_timeout(Duration::from_secs(5)), Ok(Vec::from("split across a timeout")));
+    assert_eq!(reader.get_reader().read_timeout().unwrap(), None);
+}
+
+#[test]
+fn deadline_covers_whole_frame_test() {
+    let (mut left, right) = UnixStream::pair().unwrap();
+    let mut reader = stream::MessageReader::new("--", "bound", "endbound", right, false);
+    let frame = frame(b"trickled");
+    // every byte arrives well within the timeout, but the frame as a whole does not
+    let trickle = thread::spawn(move || {
+        for byte in frame {
+            left.write_all(&[byte]).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        left
+    });
+
+    let started = Instant::now();
+    assert!(is_timeout(&reader.read_next_message_timeout(Duration::from_millis(60)).unwrap_err()));
+    assert!(started.elapsed() < Duration::from_millis(200));
+
+    let _left = trickle.join().unwrap();
+    assert_eq!(reader.read_next_message_timeout(Duration::from_secs(5)), Ok(Vec::from("trickled")));
+}
+
+#[test]
+fn stream_timeout_is_restored_test() {
+    let (_left, right) = UnixStream::pair().unwrap();
+    right.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+    let mut reader = stream::MessageReader::new("--", "bound", "endbound", right, false);
+
+    assert!(reader.read_next_message_timeout(Duration::from_millis(10)).is_err());
+    assert_eq!(reader.get_reader().read_timeout().unwrap(), Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn dual_messenger_keeps_partial_frame_test() {
+    let (mut left, right) = UnixStream::pair().unwrap();
+    right.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    let mut messenger = stream::DualMessenger::new("--", "bound", "endbound", right, false);
+    let frame = frame(b"resumed");
+    let (first, second) = frame.split_at(5);
+
+    left.write_all(first).unwrap();
+    assert!(is_timeout(&messenger.read_next_message().unwrap_err()));
+    left.write_all(second).unwrap();
+    assert_eq!(messenger.read_next_message(), Ok(Vec::from("resumed")));
+}
@@ -1,47 +1,63 @@
 extern crate messenger_plus;
 
-use std::io::{Write, Result};
+use messenger_plus::stream;
+use messenger_plus::stream::memory;
+
+use std::io::{self, Read, Write};
 use std::ops::Add;
 use std::mem;
 
-#[derive(Debug)]
-struct RandomWrite {
-    info: Vec<u8>,
-}
-
-impl RandomWrite {
-    fn new() -> RandomWrite {
-        RandomWrite {
-            info: Vec::new(),
-        }
-    }
-}
-
-impl Write for RandomWrite {
-
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let mut count = 0;
-        for i in buf {
-            self.info.push(*i);
-            count = count + 1;
-        }
-        Ok(count)
-    }
-
-    fn flush(&mut self) -> Result<()> {
-        Ok(())
-    }
-
-}
-
 #[test]
 fn writes_message_properly() {
-    let mut writer = RandomWrite::new();
+    let writer = Vec::new();
     let buf: &[u8] = "hello, world!".as_ref();
     let mut message_writer = messenger_plus::stream::MessageWriter::new("--", "bound", "endbound", writer, false);
     let _ = message_writer.write(buf);
 
     let payload_vec = Vec::from(String::from("--bound").add(mem::size_of_val(buf).to_string().as_str()).add("--hello, world!--endbound--"));
 
-    assert_eq!(message_writer.get_writer().info, payload_vec);
-}
\ No newline at end of file
+    assert_eq!(*message_writer.get_writer(), payload_vec);
+}
+
+#[test]
+fn write_that_would_block_keeps_rest_of_frame_test() {
+    let (left, mut right) = memory::duplex_with_capacity(16);
+    left.set_nonblocking(true);
+    right.set_nonblocking(true);
+    let mut message_writer = stream::MessageWriter::new("--", "bound", "endbound", left, false);
+    let frame = b"--bound13--hello, world!--endbound--";
+
+    assert_eq!(message_writer.write(b"hello, world!").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(message_writer.pending_bytes(), frame.len() - 16);
+
+    let mut received = Vec::new();
+    let mut buf = [0; 16];
+    while received.len() < frame.len() {
+        let count = right.read(&mut buf).unwrap();
+        received.extend_from_slice(&buf[..count]);
+        let _ = message_writer.flush();
+    }
+    assert_eq!(received, &frame[..]);
+    assert_eq!(message_writer.pending_bytes(), 0);
+}
+
+#[test]
+fn write_that_fails_keeps_its_sequence_number_test() {
+    let config = stream::StreamConfiguration::new("--", "bound", "endbound", false).with_sequence_numbers(true);
+    let (left, mut right) = memory::duplex_with_capacity(256);
+    left.set_nonblocking(true);
+    let mut filler = stream::TryCloneStream::try_clone_stream(&left).unwrap();
+    let mut message_writer = stream::MessageWriter::new_from_config(config.clone(), left);
+
+    // with the stream full, none of the frame goes out
+    filler.write_all(&[0; 256]).unwrap();
+    assert_eq!(message_writer.write(b"first").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(message_writer.pending_bytes(), 0);
+    right.read_exact(&mut [0; 256]).unwrap();
+
+    assert!(message_writer.write(b"first").unwrap() > 5);
+    assert!(message_writer.write(b"second").unwrap() > 6);
+    let mut message_reader = stream::MessageReader::new_from_config(config, right);
+    assert_eq!(message_reader.read_next_message(), Ok(Vec::from("first")));
+    assert_eq!(message_reader.read_next_message(), Ok(Vec::from("second")));
+}
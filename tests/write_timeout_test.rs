@@ -0,0 +1,59 @@
+#![cfg(unix)]
+extern crate messenger_plus;
+
+use messenger_plus::stream;
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+// far more than a socket pair buffers, so the write cannot finish while nobody reads
+const LARGE: usize = 8 * 1024 * 1024;
+
+fn read_messages(stream: UnixStream, count: usize) -> thread::JoinHandle<Vec<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut reader = stream::MessageReader::new("--", "bound", "endbound", stream, false);
+        (0..count).map(|_| reader.read_next_message().unwrap()).collect()
+    })
+}
+
+#[test]
+fn timed_out_frame_is_finished_first_test() {
+    let (left, right) = UnixStream::pair().unwrap();
+    let mut writer = stream::MessageWriter::new("--", "bound", "endbound", left, false);
+    let large = vec![7; LARGE];
+
+    let (written, remaining) = match *writer.write_timeout(&large, Duration::from_millis(50)).unwrap_err().kind() {
+        stream::ErrorKind::WriteTimedOut { written, remaining } => (written, remaining),
+        ref other => panic!("unexpected error {:?}", other),
+    };
+    assert!(written > 0 && remaining > 0);
+    assert_eq!(writer.pending_bytes(), remaining);
+    assert!(written + remaining > LARGE);
+    assert_eq!(writer.get_writer().write_timeout().unwrap(), None);
+
+    // the next message waits behind the rest of the large one
+    let reading = read_messages(right, 2);
+    assert!(writer.write(b"after").unwrap() > 5);
+    writer.flush().unwrap();
+    assert_eq!(writer.pending_bytes(), 0);
+    assert_eq!(reading.join().unwrap(), vec![large, Vec::from("after")]);
+}
+
+#[test]
+fn wait_writable_test() {
+    let (left, right) = UnixStream::pair().unwrap();
+    let mut writer = stream::MessageWriter::new("--", "bound", "endbound", left, false);
+    let large = vec![1; LARGE];
+
+    assert!(writer.write_timeout(&large, Duration::from_millis(20)).is_err());
+    assert!(writer.wait_writable(Duration::from_millis(20)).is_err());
+    assert!(writer.pending_bytes() > 0);
+
+    let reading = read_messages(right, 2);
+    writer.wait_writable(Duration::from_secs(10)).unwrap();
+    assert_eq!(writer.pending_bytes(), 0);
+    assert!(writer.write_timeout(b"small", Duration::from_secs(10)).unwrap() > 5);
+    assert_eq!(reading.join().unwrap(), vec![large, Vec::from("small")]);
+}